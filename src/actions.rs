@@ -0,0 +1,197 @@
+use bevy::{input::InputSystem, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::settings::KeyBindings;
+
+/// Everything the player can do, whatever device it is done with.
+/// Systems read the held actions from `Input<Action>` instead of the raw keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Pause,
+    Back,
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Action::MoveLeft => "Move Left",
+            Action::MoveRight => "Move Right",
+            Action::Jump => "Jump",
+            Action::Pause => "Pause",
+            Action::Back => "Back",
+        })
+    }
+}
+
+pub const ACTIONS: [Action; 5] = [
+    Action::MoveLeft,
+    Action::MoveRight,
+    Action::Jump,
+    Action::Pause,
+    Action::Back,
+];
+
+/// Moving around the menus, kept apart from [`Action`] as these are not rebound:
+/// arrow keys and Tab move the focus, Enter and Space activate it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MenuAction {
+    Previous,
+    Next,
+    Activate,
+}
+
+const MENU_ACTIONS: [MenuAction; 3] =
+    [MenuAction::Previous, MenuAction::Next, MenuAction::Activate];
+
+/// Present while the keyboard is used for something else than actions,
+/// like typing a name or picking a key to bind.
+#[derive(Resource, Default)]
+pub struct KeyboardCaptured;
+
+fn menu_keys_pressed(action: MenuAction, keyboard_input: &Input<KeyCode>) -> bool {
+    let shift = keyboard_input.any_pressed([KeyCode::LShift, KeyCode::RShift]);
+    let tab = keyboard_input.pressed(KeyCode::Tab);
+    match action {
+        MenuAction::Previous => {
+            keyboard_input.any_pressed([KeyCode::Up, KeyCode::Left]) || shift && tab
+        }
+        MenuAction::Next => {
+            keyboard_input.any_pressed([KeyCode::Down, KeyCode::Right]) || !shift && tab
+        }
+        MenuAction::Activate => {
+            keyboard_input.any_pressed([KeyCode::Return, KeyCode::NumpadEnter, KeyCode::Space])
+        }
+    }
+}
+
+/// How far a stick has to be pushed to count as pressed.
+const STICK_THRESHOLD: f32 = 0.5;
+
+/// The gamepad buttons, and the stick direction, doing the same thing.
+struct GamepadBinding {
+    buttons: &'static [GamepadButtonType],
+    /// The axis and the side of it the stick is pushed to.
+    stick: Option<(GamepadAxisType, f32)>,
+}
+
+impl GamepadBinding {
+    fn pressed(
+        &self,
+        gamepad: Gamepad,
+        buttons: &Input<GamepadButton>,
+        axes: &Axis<GamepadAxis>,
+    ) -> bool {
+        let button_pressed = self
+            .buttons
+            .iter()
+            .any(|button| buttons.pressed(GamepadButton::new(gamepad, *button)));
+        let stick_pushed = self.stick.is_some_and(|(axis, side)| {
+            let value = axes.get(GamepadAxis::new(gamepad, axis)).unwrap_or(0.);
+            value * side >= STICK_THRESHOLD
+        });
+        button_pressed || stick_pushed
+    }
+}
+
+fn gamepad_binding(action: Action) -> GamepadBinding {
+    match action {
+        Action::MoveLeft => GamepadBinding {
+            buttons: &[GamepadButtonType::DPadLeft],
+            stick: Some((GamepadAxisType::LeftStickX, -1.)),
+        },
+        Action::MoveRight => GamepadBinding {
+            buttons: &[GamepadButtonType::DPadRight],
+            stick: Some((GamepadAxisType::LeftStickX, 1.)),
+        },
+        Action::Jump => GamepadBinding {
+            buttons: &[GamepadButtonType::South],
+            stick: None,
+        },
+        Action::Pause => GamepadBinding {
+            buttons: &[GamepadButtonType::Start],
+            stick: None,
+        },
+        Action::Back => GamepadBinding {
+            buttons: &[GamepadButtonType::East],
+            stick: None,
+        },
+    }
+}
+
+fn menu_gamepad_binding(action: MenuAction) -> GamepadBinding {
+    match action {
+        MenuAction::Previous => GamepadBinding {
+            buttons: &[GamepadButtonType::DPadUp],
+            stick: Some((GamepadAxisType::LeftStickY, 1.)),
+        },
+        MenuAction::Next => GamepadBinding {
+            buttons: &[GamepadButtonType::DPadDown],
+            stick: Some((GamepadAxisType::LeftStickY, -1.)),
+        },
+        MenuAction::Activate => GamepadBinding {
+            buttons: &[GamepadButtonType::South],
+            stick: None,
+        },
+    }
+}
+
+/// Systems updating `Input<Action>` and `Input<MenuAction>` from the devices,
+/// right after bevy reads them.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct UpdateActions;
+
+pub struct ActionPlugin;
+
+impl Plugin for ActionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Input<Action>>()
+            .init_resource::<Input<MenuAction>>()
+            .add_system(
+                update_actions
+                    .in_set(UpdateActions)
+                    .in_base_set(CoreSet::PreUpdate)
+                    .after(InputSystem),
+            );
+    }
+}
+
+fn update_actions(
+    keyboard_input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    keyboard_captured: Option<Res<KeyboardCaptured>>,
+    mut actions: ResMut<Input<Action>>,
+    mut menu_actions: ResMut<Input<MenuAction>>,
+) {
+    let gamepad_pressed = |binding: GamepadBinding| {
+        gamepads
+            .iter()
+            .any(|gamepad| binding.pressed(gamepad, &gamepad_buttons, &gamepad_axes))
+    };
+    let keyboard = keyboard_captured.is_none();
+    actions.clear();
+    for action in ACTIONS {
+        if keyboard && keyboard_input.pressed(key_bindings.key(action))
+            || gamepad_pressed(gamepad_binding(action))
+        {
+            actions.press(action);
+        } else {
+            actions.release(action);
+        }
+    }
+    menu_actions.clear();
+    for action in MENU_ACTIONS {
+        if keyboard && menu_keys_pressed(action, &keyboard_input)
+            || gamepad_pressed(menu_gamepad_binding(action))
+        {
+            menu_actions.press(action);
+        } else {
+            menu_actions.release(action);
+        }
+    }
+}
@@ -1,21 +1,225 @@
 use bevy::prelude::*;
 use bevy::utils::HashMap;
+use serde::Deserialize;
 
 use crate::player::Facing;
 use crate::AppState;
 
-#[derive(Component, Clone)]
-pub struct Animation {
-    pub handle: Handle<TextureAtlas>,
-    pub curr: usize,
-    pub last: usize,
-    pub fps: usize,
+/// What a clip does once it shows its last frame.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+pub enum PlayMode {
+    /// Starts over from its first frame.
+    #[default]
+    Loop,
+    /// Stays on its last frame, or goes on with the clip given as `next`.
+    Once { next: Option<String> },
 }
 
-#[derive(Resource)]
-pub struct Animations {
-    pub map: HashMap<String, Animation>,
-    pub active: Animation,
+/// A test on one of the parameters an entity sets on its [`Animator`]. Parameters it
+/// never set read as 0.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub enum Condition {
+    Above(String, f32),
+    Below(String, f32),
+}
+
+impl Condition {
+    fn holds(&self, parameters: &HashMap<String, f32>) -> bool {
+        let value = |name: &String| parameters.get(name).copied().unwrap_or_default();
+        match self {
+            Condition::Above(name, threshold) => value(name) > *threshold,
+            Condition::Below(name, threshold) => value(name) < *threshold,
+        }
+    }
+}
+
+/// Switches to the clip `to` once all of its conditions hold.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Transition {
+    pub to: String,
+    #[serde(default)]
+    pub when: Vec<Condition>,
+}
+
+/// A run of frames of a texture atlas, shown one after the other.
+#[derive(Debug, Clone)]
+pub struct Clip {
+    pub atlas: Handle<TextureAtlas>,
+    /// Atlas index of the first frame.
+    pub first: usize,
+    /// Number of frames, taken from `first` on.
+    pub len: usize,
+    pub fps: f32,
+    pub mode: PlayMode,
+}
+
+impl Clip {
+    /// A looping clip of the first `len` frames of the atlas.
+    pub fn looping(atlas: Handle<TextureAtlas>, len: usize, fps: f32) -> Clip {
+        Clip {
+            atlas,
+            first: 0,
+            len,
+            fps,
+            mode: PlayMode::Loop,
+        }
+    }
+
+    fn frame_timer(&self) -> Timer {
+        Timer::from_seconds(1. / self.fps, TimerMode::Repeating)
+    }
+}
+
+/// The clips an entity can play, by name. Entities that look the same share them, e.g. the
+/// player and its ghost.
+#[derive(Debug, Clone, Default)]
+pub struct Clips(HashMap<String, Clip>);
+
+impl Clips {
+    pub fn new<'a>(clips: impl IntoIterator<Item = (&'a str, Clip)>) -> Clips {
+        Clips(
+            clips
+                .into_iter()
+                .map(|(name, clip)| (name.to_owned(), clip))
+                .collect(),
+        )
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Clip> {
+        self.0.get(name)
+    }
+}
+
+/// Plays the clips of one entity, one at a time. The entity picks its clip with
+/// [`Animator::play`], or sets parameters with [`Animator::set`] and lets the first of its
+/// transitions whose conditions hold pick it. A one-shot clip is played to its end before
+/// the transitions are tried again, and moves on to its `next` clip by itself.
+#[derive(Component, Debug)]
+pub struct Animator {
+    clips: Clips,
+    current: String,
+    /// Index of the shown frame, inside the current clip.
+    frame: usize,
+    timer: Timer,
+    finished: bool,
+    transitions: Vec<Transition>,
+    parameters: HashMap<String, f32>,
+    /// Whether the frames only change through [`Animator::seek`].
+    pub paused: bool,
+}
+
+impl Animator {
+    /// Starts on the clip named `initial`, which has to be one of the `clips`.
+    pub fn new(clips: Clips, initial: &str) -> Animator {
+        let timer = clips.0[initial].frame_timer();
+        Animator {
+            clips,
+            current: initial.to_owned(),
+            frame: 0,
+            timer,
+            finished: false,
+            transitions: Vec::new(),
+            parameters: HashMap::new(),
+            paused: false,
+        }
+    }
+
+    /// Picks the clip with the given transitions, tried in order, on every tick.
+    pub fn with_transitions(mut self, transitions: Vec<Transition>) -> Animator {
+        self.transitions = transitions;
+        self
+    }
+
+    /// Sets a parameter tested by the transitions.
+    pub fn set(&mut self, parameter: &str, value: f32) {
+        if let Some(current) = self.parameters.get_mut(parameter) {
+            *current = value;
+        } else {
+            self.parameters.insert(parameter.to_owned(), value);
+        }
+    }
+
+    /// Switches to the clip from its first frame, unless it is already playing. Unknown
+    /// clips are ignored.
+    pub fn play(&mut self, name: &str) {
+        if name == self.current {
+            return;
+        }
+        let Some(clip) = self.clips.get(name) else {
+            warn!("There is no {name} animation clip");
+            return;
+        };
+        self.timer = clip.frame_timer();
+        self.current = name.to_owned();
+        self.frame = 0;
+        self.finished = false;
+    }
+
+    /// Shows the given frame of the current clip.
+    pub fn seek(&mut self, frame: usize) {
+        self.frame = frame.min(self.clip().len.saturating_sub(1));
+        self.timer.reset();
+    }
+
+    pub fn current(&self) -> &str {
+        &self.current
+    }
+
+    /// Index of the shown frame, inside the current clip.
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    /// Whether a one-shot clip shows its last frame for good.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn clips(&self) -> &Clips {
+        &self.clips
+    }
+
+    fn clip(&self) -> &Clip {
+        &self.clips.0[&self.current]
+    }
+
+    fn tick(&mut self, delta: std::time::Duration) {
+        // A one-shot clip plays to its end before the transitions get a say
+        let interruptible = self.finished || self.clip().mode == PlayMode::Loop;
+        if let Some(transition) = self.transitions.iter().find(|transition| {
+            interruptible
+                && transition
+                    .when
+                    .iter()
+                    .all(|condition| condition.holds(&self.parameters))
+        }) {
+            let to = transition.to.clone();
+            self.play(&to);
+        }
+        if self.paused || self.finished {
+            return;
+        }
+        self.timer.tick(delta);
+        for _ in 0..self.timer.times_finished_this_tick() {
+            let clip = self.clip();
+            if self.frame + 1 < clip.len {
+                self.frame += 1;
+                continue;
+            }
+            match clip.mode.clone() {
+                PlayMode::Loop => self.frame = 0,
+                PlayMode::Once { next: Some(next) } => {
+                    // The frames left this tick were counted with the timer of the old clip
+                    self.play(&next);
+                    break;
+                }
+                PlayMode::Once { next: None } => {
+                    self.finished = true;
+                    break;
+                }
+            }
+        }
+    }
 }
 
 pub struct AnimatorPlugin;
@@ -26,22 +230,111 @@ impl Plugin for AnimatorPlugin {
     }
 }
 
-#[derive(Component, Deref, DerefMut)]
-pub struct AnimationTimer(pub Timer);
-
 fn animate_sprites(
     time: Res<Time>,
-    animations: Res<Animations>,
-    mut query: Query<(&mut AnimationTimer, &mut TextureAtlasSprite, &Facing)>,
+    mut query: Query<(
+        &mut Animator,
+        &mut Handle<TextureAtlas>,
+        &mut TextureAtlasSprite,
+        Option<&Facing>,
+    )>,
 ) {
-    for (mut timer, mut sprite, facing) in &mut query {
-        timer.tick(time.delta());
-        if timer.just_finished() {
-            sprite.index = (sprite.index + 1) % animations.active.last;
+    for (mut animator, mut atlas, mut sprite, facing) in &mut query {
+        animator.tick(time.delta());
+        let clip = animator.clip();
+        if *atlas != clip.atlas {
+            *atlas = clip.atlas.clone();
+        }
+        let index = clip.first + animator.frame;
+        if sprite.index != index {
+            sprite.index = index;
         }
         match facing {
-            Facing::Left => sprite.flip_x = true,
-            Facing::Right => sprite.flip_x = false,
+            Some(Facing::Left) => sprite.flip_x = true,
+            Some(Facing::Right) => sprite.flip_x = false,
+            None => {}
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn animator(transitions: Vec<Transition>) -> Animator {
+        let clip = |len, mode| Clip {
+            atlas: Handle::default(),
+            first: 0,
+            len,
+            fps: 10.,
+            mode,
+        };
+        let clips = Clips::new([
+            ("idle", clip(2, PlayMode::Loop)),
+            ("run", clip(4, PlayMode::Loop)),
+            ("hit", clip(2, PlayMode::Once { next: None })),
+            (
+                "land",
+                clip(
+                    2,
+                    PlayMode::Once {
+                        next: Some("idle".to_owned()),
+                    },
+                ),
+            ),
+        ]);
+        Animator::new(clips, "idle").with_transitions(transitions)
+    }
+
+    fn to(clip: &str, when: Vec<Condition>) -> Transition {
+        Transition {
+            to: clip.to_owned(),
+            when,
+        }
+    }
+
+    #[test]
+    fn picks_the_first_transition_that_holds() {
+        let mut animator = animator(vec![
+            to("run", vec![Condition::Above("speed".to_owned(), 1.)]),
+            to("idle", vec![]),
+        ]);
+        animator.tick(Duration::ZERO);
+        assert_eq!(animator.current(), "idle");
+        animator.set("speed", 2.);
+        animator.tick(Duration::ZERO);
+        assert_eq!(animator.current(), "run");
+        animator.set("speed", 0.5);
+        animator.tick(Duration::ZERO);
+        assert_eq!(animator.current(), "idle");
+    }
+
+    #[test]
+    fn plays_one_shot_clips_to_their_end_before_transitions() {
+        let mut animator = animator(vec![to("idle", vec![])]);
+        for clip in ["land", "hit"] {
+            animator.play(clip);
+            animator.tick(Duration::from_millis(50));
+            assert_eq!(animator.current(), clip);
+            animator.tick(Duration::from_millis(100));
+            assert_eq!(animator.current(), clip);
+            assert_eq!(animator.frame(), 1);
+            // Past the last frame, the next clip or the transition takes over
+            animator.tick(Duration::from_millis(100));
+            animator.tick(Duration::ZERO);
+            assert_eq!(animator.current(), "idle");
+        }
+    }
+
+    #[test]
+    fn starts_the_next_clip_from_its_first_frame() {
+        let mut animator = animator(Vec::new());
+        animator.play("land");
+        // Enough time for the two frames of land and one more
+        animator.tick(Duration::from_millis(350));
+        assert_eq!(animator.current(), "idle");
+        assert_eq!(animator.frame(), 0);
+    }
+}
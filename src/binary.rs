@@ -0,0 +1,71 @@
+//! The small binary files the game keeps, the replays and ghosts: a magic number and a
+//! version, followed by little endian fields.
+
+use std::{fs, io, path::Path};
+
+/// The file ended before all of its fields were read.
+#[derive(Debug)]
+pub struct Truncated;
+
+/// Starts a file with its magic number and version.
+pub fn header(magic: &[u8; 4], version: u8) -> Vec<u8> {
+    let mut bytes = magic.to_vec();
+    bytes.push(version);
+    bytes
+}
+
+/// Reads the fields of a file one after the other.
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader { bytes }
+    }
+
+    /// Bytes left to read.
+    pub fn remaining(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Whether the file starts with `magic`.
+    pub fn magic(&mut self, magic: &[u8; 4]) -> Result<bool, Truncated> {
+        Ok(&self.array()? == magic)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, Truncated> {
+        let (&byte, rest) = self.bytes.split_first().ok_or(Truncated)?;
+        self.bytes = rest;
+        Ok(byte)
+    }
+
+    pub fn u32(&mut self) -> Result<u32, Truncated> {
+        self.array().map(u32::from_le_bytes)
+    }
+
+    pub fn u64(&mut self) -> Result<u64, Truncated> {
+        self.array().map(u64::from_le_bytes)
+    }
+
+    pub fn f32(&mut self) -> Result<f32, Truncated> {
+        self.array().map(f32::from_le_bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Truncated> {
+        if self.bytes.len() < N {
+            return Err(Truncated);
+        }
+        let (array, rest) = self.bytes.split_at(N);
+        self.bytes = rest;
+        Ok(array.try_into().unwrap())
+    }
+}
+
+/// Writes a file, creating the directories it is in.
+pub fn save(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, bytes)
+}
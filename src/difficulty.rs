@@ -0,0 +1,456 @@
+use std::fmt;
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::{BoxedFuture, HashMap},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{recording::Playback, AppState};
+
+/// The difficulty presets, picked from the main menu.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DifficultyLevel {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+const DIFFICULTY_LEVELS: [DifficultyLevel; 3] = [
+    DifficultyLevel::Easy,
+    DifficultyLevel::Normal,
+    DifficultyLevel::Hard,
+];
+
+impl DifficultyLevel {
+    pub fn next(&self) -> DifficultyLevel {
+        match self {
+            DifficultyLevel::Easy => DifficultyLevel::Normal,
+            DifficultyLevel::Normal => DifficultyLevel::Hard,
+            DifficultyLevel::Hard => DifficultyLevel::Easy,
+        }
+    }
+
+    /// Where the curves of the preset are, inside the assets.
+    fn path(&self) -> &'static str {
+        match self {
+            DifficultyLevel::Easy => "difficulty/easy.difficulty.ron",
+            DifficultyLevel::Normal => "difficulty/normal.difficulty.ron",
+            DifficultyLevel::Hard => "difficulty/hard.difficulty.ron",
+        }
+    }
+}
+
+impl std::fmt::Display for DifficultyLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            DifficultyLevel::Easy => "Easy",
+            DifficultyLevel::Normal => "Normal",
+            DifficultyLevel::Hard => "Hard",
+        })
+    }
+}
+
+/// A value that changes with the score, given by `(score, value)` points sorted by score.
+/// It is linear between the points and flat before the first one and after the last one.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(transparent)]
+pub struct Curve(Vec<(f32, f32)>);
+
+impl Curve {
+    pub fn constant(value: f32) -> Curve {
+        Curve(vec![(0., value)])
+    }
+
+    pub fn at(&self, score: usize) -> f32 {
+        let score = score as f32;
+        let Some(&(first_score, first_value)) = self.0.first() else {
+            return 0.;
+        };
+        if score <= first_score {
+            return first_value;
+        }
+        for window in self.0.windows(2) {
+            let [(from_score, from_value), (to_score, to_value)] = [window[0], window[1]];
+            if score <= to_score {
+                let t = (score - from_score) / (to_score - from_score);
+                return from_value + (to_value - from_value) * t;
+            }
+        }
+        self.0.last().map_or(first_value, |&(_, value)| value)
+    }
+
+    /// Checks that the curve has points, sorted by score, with finite values `ok` accepts.
+    fn validate(
+        &self,
+        name: &'static str,
+        requirement: &'static str,
+        ok: fn(f32) -> bool,
+    ) -> Result<(), DifficultyError> {
+        if self.0.is_empty() {
+            return Err(DifficultyError::EmptyCurve(name));
+        }
+        if !self.0.iter().all(|(score, _)| score.is_finite())
+            || self.0.windows(2).any(|window| window[0].0 >= window[1].0)
+        {
+            return Err(DifficultyError::UnsortedCurve(name));
+        }
+        // The curve is linear between its points, so it stays between their values
+        if !self
+            .0
+            .iter()
+            .all(|&(_, value)| value.is_finite() && ok(value))
+        {
+            return Err(DifficultyError::InvalidValue {
+                curve: name,
+                requirement,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum DifficultyError {
+    Ron(ron::error::SpannedError),
+    EmptyCurve(&'static str),
+    UnsortedCurve(&'static str),
+    InvalidValue {
+        curve: &'static str,
+        requirement: &'static str,
+    },
+}
+
+impl fmt::Display for DifficultyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DifficultyError::Ron(err) => write!(f, "{err}"),
+            DifficultyError::EmptyCurve(curve) => write!(f, "the {curve} curve has no points"),
+            DifficultyError::UnsortedCurve(curve) => {
+                write!(f, "the points of the {curve} curve are not sorted by score")
+            }
+            DifficultyError::InvalidValue { curve, requirement } => {
+                write!(f, "the {curve} curve needs finite values {requirement}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DifficultyError {}
+
+impl From<ron::error::SpannedError> for DifficultyError {
+    fn from(err: ron::error::SpannedError) -> Self {
+        DifficultyError::Ron(err)
+    }
+}
+
+/// How hard a run gets as the score goes up, loaded from a `.difficulty.ron` asset.
+#[derive(Debug, Clone, Deserialize, TypeUuid)]
+#[uuid = "6f3c9a52-4b1e-4c8d-9a0e-2d7b5e81c4f3"]
+pub struct DifficultyCurves {
+    /// Seconds to reach the next wall, also what the bank timer starts with.
+    pub time_to_reach_wall: Curve,
+    /// Seconds added to the bank timer at a wall.
+    pub time_bonus: Curve,
+    /// Most parts a platform is made of, each one has between one and this many.
+    pub max_platform_parts: Curve,
+    /// How far above the lowest platforms a platform can be, in pixels.
+    pub platform_height_range: Curve,
+    /// Horizontal speed of the platforms.
+    pub platform_speed: Curve,
+    /// Most random speed added to a platform on top of its speed.
+    pub platform_speed_variation: Curve,
+}
+
+impl Default for DifficultyCurves {
+    /// The Normal preset, for when the assets are not loaded.
+    fn default() -> Self {
+        DifficultyCurves {
+            time_to_reach_wall: Curve::constant(15.),
+            time_bonus: Curve::constant(5.),
+            max_platform_parts: Curve(vec![
+                (5., 4.),
+                (6., 3.),
+                (9., 3.),
+                (10., 2.),
+                (15., 2.),
+                (16., 1.),
+            ]),
+            platform_height_range: Curve::constant(24.),
+            platform_speed: Curve::constant(125.),
+            platform_speed_variation: Curve::constant(5.),
+        }
+    }
+}
+
+impl DifficultyCurves {
+    /// Reads and checks the curves of a preset.
+    pub fn parse(bytes: &[u8]) -> Result<DifficultyCurves, DifficultyError> {
+        let curves: DifficultyCurves = ron::de::from_bytes(bytes)?;
+        curves.validate()?;
+        Ok(curves)
+    }
+
+    /// Checks that every curve has points sorted by score, and finite values the game can
+    /// use.
+    pub fn validate(&self) -> Result<(), DifficultyError> {
+        let positive = |value: f32| value > 0.;
+        let non_negative = |value: f32| value >= 0.;
+        self.time_to_reach_wall
+            .validate("time_to_reach_wall", "above 0", positive)?;
+        self.time_bonus
+            .validate("time_bonus", "of at least 0", non_negative)?;
+        self.max_platform_parts
+            .validate("max_platform_parts", "of at least 1", |value| value >= 1.)?;
+        self.platform_height_range.validate(
+            "platform_height_range",
+            "of at least 0",
+            non_negative,
+        )?;
+        self.platform_speed
+            .validate("platform_speed", "above 0", positive)?;
+        self.platform_speed_variation.validate(
+            "platform_speed_variation",
+            "of at least 0",
+            non_negative,
+        )?;
+        Ok(())
+    }
+
+    pub fn max_platform_parts(&self, score: usize) -> u32 {
+        self.max_platform_parts.at(score).round().max(1.) as u32
+    }
+}
+
+/// The difficulty of the current run.
+#[derive(Resource, Debug, Default)]
+pub struct Difficulty {
+    pub level: DifficultyLevel,
+    pub curves: DifficultyCurves,
+}
+
+#[derive(Default)]
+struct DifficultyLoader;
+
+impl AssetLoader for DifficultyLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let curves = DifficultyCurves::parse(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(curves));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["difficulty.ron"]
+    }
+}
+
+/// The curves of every preset, loaded on startup. Empty without an [`AssetServer`].
+#[derive(Resource)]
+pub struct DifficultyHandles(HashMap<DifficultyLevel, Handle<DifficultyCurves>>);
+
+impl FromWorld for DifficultyHandles {
+    fn from_world(world: &mut World) -> Self {
+        let Some(asset_server) = world.get_resource::<AssetServer>() else {
+            return DifficultyHandles(HashMap::default());
+        };
+        DifficultyHandles(
+            DIFFICULTY_LEVELS
+                .into_iter()
+                .map(|level| (level, asset_server.load(level.path())))
+                .collect(),
+        )
+    }
+}
+
+pub struct DifficultyPlugin;
+
+impl Plugin for DifficultyPlugin {
+    fn build(&self, app: &mut App) {
+        // Headless, the presets are not loaded and every run uses the default curves
+        if app.world.contains_resource::<AssetServer>() {
+            app.add_asset::<DifficultyCurves>()
+                .init_asset_loader::<DifficultyLoader>();
+        }
+        app.init_resource::<DifficultyHandles>()
+            .init_resource::<Difficulty>()
+            .init_resource::<DifficultyLevel>()
+            .add_system(choose_difficulty.in_schedule(OnEnter(AppState::InGame)))
+            .add_system(reload_difficulty.run_if(resource_exists::<Assets<DifficultyCurves>>()));
+    }
+}
+
+pub fn choose_difficulty(
+    mut difficulty: ResMut<Difficulty>,
+    difficulty_level: Res<DifficultyLevel>,
+    playback: Option<Res<Playback>>,
+    handles: Res<DifficultyHandles>,
+    assets: Option<Res<Assets<DifficultyCurves>>>,
+) {
+    // A replay keeps the difficulty it was recorded with
+    let level = playback.map_or(*difficulty_level, |playback| playback.difficulty);
+    let Some(assets) = assets else {
+        *difficulty = Difficulty {
+            level,
+            curves: DifficultyCurves::default(),
+        };
+        return;
+    };
+    let curves = match assets.get(&handles.0[&level]) {
+        Some(curves) => curves.clone(),
+        None => {
+            warn!("The {level} difficulty is not loaded, using the default one");
+            DifficultyCurves::default()
+        }
+    };
+    *difficulty = Difficulty { level, curves };
+}
+
+/// Applies the changes made to the difficulty of the current run, when assets are watched.
+fn reload_difficulty(
+    mut events: EventReader<AssetEvent<DifficultyCurves>>,
+    mut difficulty: ResMut<Difficulty>,
+    handles: Res<DifficultyHandles>,
+    assets: Res<Assets<DifficultyCurves>>,
+) {
+    for event in events.iter() {
+        let AssetEvent::Modified { handle } = event else {
+            continue;
+        };
+        if *handle != handles.0[&difficulty.level] {
+            continue;
+        }
+        if let Some(curves) = assets.get(handle) {
+            info!("Reloaded the {} difficulty", difficulty.level);
+            difficulty.curves = curves.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn curves(time_bonus: &str) -> String {
+        format!(
+            "(
+                time_to_reach_wall: [(0, 15)],
+                time_bonus: {time_bonus},
+                max_platform_parts: [(0, 4)],
+                platform_height_range: [(0, 24)],
+                platform_speed: [(0, 125)],
+                platform_speed_variation: [(0, 5)],
+            )"
+        )
+    }
+
+    #[test]
+    fn curve_is_flat_before_the_first_point() {
+        let curve = Curve(vec![(5., 10.), (15., 20.)]);
+        assert_eq!(curve.at(0), 10.);
+        assert_eq!(curve.at(5), 10.);
+    }
+
+    #[test]
+    fn curve_is_linear_between_points() {
+        let curve = Curve(vec![(5., 10.), (15., 20.), (25., 0.)]);
+        assert_eq!(curve.at(6), 11.);
+        assert_eq!(curve.at(15), 20.);
+        assert_eq!(curve.at(20), 10.);
+    }
+
+    #[test]
+    fn curve_is_flat_after_the_last_point() {
+        let curve = Curve(vec![(5., 10.), (15., 20.)]);
+        assert_eq!(curve.at(16), 20.);
+        assert_eq!(curve.at(1000), 20.);
+        assert_eq!(Curve::constant(3.).at(1000), 3.);
+    }
+
+    #[test]
+    fn shipped_presets_are_valid() {
+        for preset in [
+            include_str!("../assets/difficulty/easy.difficulty.ron"),
+            include_str!("../assets/difficulty/normal.difficulty.ron"),
+            include_str!("../assets/difficulty/hard.difficulty.ron"),
+        ] {
+            DifficultyCurves::parse(preset.as_bytes()).unwrap();
+        }
+        DifficultyCurves::default().validate().unwrap();
+    }
+
+    #[test]
+    fn rejects_invalid_curves() {
+        let parse = |time_bonus: &str| DifficultyCurves::parse(curves(time_bonus).as_bytes());
+        assert!(parse("[(0, 5)]").is_ok());
+        assert!(parse("[(0, 0)]").is_ok());
+        assert!(matches!(
+            parse("[]"),
+            Err(DifficultyError::EmptyCurve("time_bonus"))
+        ));
+        assert!(matches!(
+            parse("[(5, 5), (0, 5)]"),
+            Err(DifficultyError::UnsortedCurve("time_bonus"))
+        ));
+        assert!(matches!(
+            parse("[(5, 5), (5, 6)]"),
+            Err(DifficultyError::UnsortedCurve("time_bonus"))
+        ));
+        assert!(matches!(
+            parse("[(0, 5), (10, -1)]"),
+            Err(DifficultyError::InvalidValue {
+                curve: "time_bonus",
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse("[(0, 5), (10, inf)]"),
+            Err(DifficultyError::InvalidValue {
+                curve: "time_bonus",
+                ..
+            })
+        ));
+        assert!(matches!(parse("[(0, 5"), Err(DifficultyError::Ron(_))));
+    }
+
+    #[test]
+    fn rejects_non_positive_and_infinite_durations_and_speeds() {
+        for curves in [
+            DifficultyCurves {
+                time_to_reach_wall: Curve::constant(0.),
+                ..default()
+            },
+            DifficultyCurves {
+                platform_speed: Curve::constant(-125.),
+                ..default()
+            },
+            DifficultyCurves {
+                max_platform_parts: Curve::constant(0.),
+                ..default()
+            },
+            // Durations and speeds end up in a `Duration`, which panics on them
+            DifficultyCurves {
+                time_to_reach_wall: Curve::constant(f32::INFINITY),
+                ..default()
+            },
+            DifficultyCurves {
+                platform_speed: Curve::constant(f32::INFINITY),
+                ..default()
+            },
+            DifficultyCurves {
+                platform_speed: Curve::constant(f32::NAN),
+                ..default()
+            },
+        ] {
+            assert!(curves.validate().is_err());
+        }
+    }
+}
@@ -1,24 +1,39 @@
-use bevy::{prelude::*, window::PrimaryWindow};
+use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
-use rand::{random, seq::SliceRandom};
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
 
-use crate::{events::WallReached, AppState};
+use crate::{
+    events::{Landed, WallReached},
+    pause::PauseState,
+    physics::Arena,
+    recording::Playback,
+    rng::EffectRng,
+    settings::Volume,
+    shake::CameraShake,
+    tiles::PlatformSpeed,
+    AppState, GameMode, GameplaySet,
+};
 
-#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Effect {
     Earthquake,
     FastPlatforms,
+    SlowPlatforms,
     InverseKeyboard,
     FallthroughPlatforms,
     HighGravity,
     LowGravity,
     Darkness,
     IcyPlatforms,
+    SlowMo,
+    FastMo,
+    ViolentLandings,
 }
 
-impl ToString for Effect {
-    fn to_string(&self) -> String {
-        format!("{:?}", self)
+impl std::fmt::Display for Effect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = format!("{:?}", self)
             .chars()
             .fold(String::new(), |mut acc, c| {
                 if !acc.is_empty() && c.is_uppercase() {
@@ -26,98 +41,324 @@ impl ToString for Effect {
                 }
                 acc.push(c);
                 acc
-            })
+            });
+        f.write_str(&name)
+    }
+}
+
+impl Effect {
+    /// Whether the two effects cannot be active at the same time.
+    /// When stacking, the newer effect replaces the one it conflicts with.
+    pub fn conflicts_with(&self, other: &Effect) -> bool {
+        matches!(
+            (self, other),
+            (Effect::HighGravity, Effect::LowGravity)
+                | (Effect::LowGravity, Effect::HighGravity)
+                | (Effect::SlowMo, Effect::FastMo)
+                | (Effect::FastMo, Effect::SlowMo)
+                | (Effect::FastPlatforms, Effect::SlowPlatforms)
+                | (Effect::SlowPlatforms, Effect::FastPlatforms)
+        )
     }
 }
 
 const EFFECTS: &[Effect] = &[
     Effect::Earthquake,
     Effect::FastPlatforms,
+    Effect::SlowPlatforms,
     Effect::FallthroughPlatforms,
     Effect::HighGravity,
     Effect::LowGravity,
     Effect::InverseKeyboard,
     Effect::Darkness,
     Effect::IcyPlatforms,
+    Effect::SlowMo,
+    Effect::FastMo,
+    Effect::ViolentLandings,
 ];
 
+/// Upcoming effects, drawn from the back every time a wall is reached.
 #[derive(Resource, Deref, DerefMut, Debug)]
 pub struct EffectQueue(pub Vec<Effect>);
 
+/// The effects currently applied to the game, oldest first.
+#[derive(Resource, Deref, DerefMut, Debug, Default)]
+pub struct ActiveEffects(pub Vec<Effect>);
+
+impl ActiveEffects {
+    /// Activates `effect` on top of the current ones, replacing any conflicting effect
+    /// and dropping the oldest ones once there are more than `capacity` effects.
+    pub fn stack(&mut self, effect: Effect, capacity: usize) {
+        self.retain(|active| !active.conflicts_with(&effect));
+        self.push(effect);
+        let overflow = self.len().saturating_sub(capacity.max(1));
+        self.drain(..overflow);
+    }
+}
+
+/// How many effects can be active at once in [`GameMode::Hard`].
+#[derive(Resource, Deref, DerefMut)]
+pub struct MaxStackedEffects(pub usize);
+
+impl Default for MaxStackedEffects {
+    fn default() -> Self {
+        MaxStackedEffects(3)
+    }
+}
+
+/// How a time-bending effect changes the speed of the game.
+#[derive(Debug, Clone, Copy)]
+pub struct TimeScaleEffect {
+    pub speed: f32,
+    /// Whether the wall timer slows down or speeds up along with the game.
+    pub scales_wall_timer: bool,
+}
+
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct TimeScaleEffects {
+    pub slow_mo: TimeScaleEffect,
+    pub fast_mo: TimeScaleEffect,
+}
+
+impl Default for TimeScaleEffects {
+    fn default() -> Self {
+        TimeScaleEffects {
+            // The clock keeps running at full speed while everything else crawls
+            slow_mo: TimeScaleEffect {
+                speed: 0.5,
+                scales_wall_timer: false,
+            },
+            // Otherwise going fast would just be free extra time
+            fast_mo: TimeScaleEffect {
+                speed: 1.5,
+                scales_wall_timer: true,
+            },
+        }
+    }
+}
+
+/// Game-wide time scale. The game speed is applied to Bevy's [`Time`], which drives
+/// Rapier, animations and timers, while the wall timer follows its own scale.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct TimeScale {
+    pub game: f32,
+    pub wall_timer: f32,
+}
+
+impl Default for TimeScale {
+    fn default() -> Self {
+        TimeScale {
+            game: 1.,
+            wall_timer: 1.,
+        }
+    }
+}
+
+/// Trauma kept up while the earthquake is active.
+const EARTHQUAKE_TRAUMA: f32 = 0.5;
+/// Trauma added by the hardest landing while violent landings are active.
+const MAX_LANDING_TRAUMA: f32 = 0.9;
+/// Landings slower than this do not shake the screen, e.g. when walking on a platform.
+const MIN_LANDING_SHAKE_FALL_SPEED: f32 = 40.;
+/// Landings at this speed or faster shake the screen the most.
+const MAX_LANDING_SHAKE_FALL_SPEED: f32 = 400.;
+
 pub struct EffectsPlugin;
 
 impl Plugin for EffectsPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(EffectQueue(vec![]))
-            .add_systems((random_effect, play_sound_effect).chain())
+            .init_resource::<GameMode>()
+            .init_resource::<ActiveEffects>()
+            .init_resource::<MaxStackedEffects>()
+            .init_resource::<TimeScaleEffects>()
+            .init_resource::<TimeScale>()
+            .add_system(clear_effects.in_schedule(OnEnter(AppState::InGame)))
+            .add_system(reset_time_scale.in_schedule(OnExit(AppState::InGame)))
+            .add_system(
+                random_effect
+                    .in_set(GameplaySet::Events)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_systems(
+                (change_gravity, change_time_scale, change_platform_speed)
+                    .after(random_effect)
+                    .in_set(GameplaySet::Events)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            // Sound and camera shake are left out when running headless
+            .add_systems((
+                play_sound_effect.run_if(resource_exists::<Audio>()),
+                apply_time_scale,
+            ))
             .add_systems(
                 (
-                    shake_camera,
-                    change_gravity,
+                    earthquake
+                        .run_if(resource_exists::<CameraShake>())
+                        .run_if(state_exists_and_equals(PauseState::Running)),
                     apply_darkness,
                     remove_darkness,
+                    shake_on_landing.run_if(resource_exists::<CameraShake>()),
                 )
-                    .after(random_effect)
                     .in_set(OnUpdate(AppState::InGame)),
             );
     }
 }
 
-fn random_effect(mut effect_q: ResMut<EffectQueue>, mut event_reader: EventReader<WallReached>) {
-    if event_reader.iter().next().is_some() {
-        effect_q.pop();
-        if effect_q.is_empty() {
-            let mut effects = EFFECTS.to_vec();
-            effects.shuffle(&mut rand::thread_rng());
-            effect_q.0 = effects;
-        }
+fn clear_effects(mut effect_q: ResMut<EffectQueue>, mut active_effects: ResMut<ActiveEffects>) {
+    // Start every run from a fresh queue so the effect order only depends on the seed
+    effect_q.clear();
+    active_effects.clear();
+}
+
+fn random_effect(
+    game_mode: Res<GameMode>,
+    playback: Option<Res<Playback>>,
+    max_stacked: Res<MaxStackedEffects>,
+    mut rng: ResMut<EffectRng>,
+    mut effect_q: ResMut<EffectQueue>,
+    mut active_effects: ResMut<ActiveEffects>,
+    mut event_reader: EventReader<WallReached>,
+) {
+    if event_reader.iter().next().is_none() {
+        return;
     }
+    // Skip effects that are already active, refilling the queue when it runs out
+    let effect = loop {
+        match effect_q.pop() {
+            Some(effect) if active_effects.contains(&effect) => continue,
+            Some(effect) => break effect,
+            None => {
+                let mut effects: Vec<Effect> = EFFECTS
+                    .iter()
+                    .filter(|effect| !active_effects.contains(effect))
+                    .copied()
+                    .collect();
+                if effects.is_empty() {
+                    return;
+                }
+                effects.shuffle(&mut rng.0);
+                effect_q.0 = effects;
+            }
+        }
+    };
+    // A replay keeps the mode it was recorded with
+    let game_mode = playback.map_or(*game_mode, |playback| playback.mode);
+    let capacity = match game_mode {
+        GameMode::Normal => 1,
+        GameMode::Hard => max_stacked.0,
+    };
+    active_effects.stack(effect, capacity);
 }
 
 fn play_sound_effect(
-    effect_q: Res<EffectQueue>,
+    active_effects: Res<ActiveEffects>,
     asset_server: Res<AssetServer>,
     audio: Res<Audio>,
+    volume: Res<Volume>,
 ) {
-    if effect_q.is_changed() {
-        let Some(effect) = effect_q.last() else {
+    if active_effects.is_changed() {
+        let Some(effect) = active_effects.last() else {
             return;
         };
         let sound_effect = asset_server.load(format!(
             "sounds/{}.wav",
             effect.to_string().replace(' ', "")
         ));
-        audio.play(sound_effect);
+        audio.play_with_settings(sound_effect, PlaybackSettings::ONCE.with_volume(volume.0));
+    }
+}
+
+fn earthquake(active_effects: Res<ActiveEffects>, mut shake: ResMut<CameraShake>) {
+    if active_effects.contains(&Effect::Earthquake) {
+        shake.sustain_trauma(EARTHQUAKE_TRAUMA);
     }
 }
 
-fn shake_camera(
-    effect_q: Res<EffectQueue>,
-    mut camera_query: Query<&mut OrthographicProjection, With<Camera>>,
+fn shake_on_landing(
+    active_effects: Res<ActiveEffects>,
+    mut landed_events: EventReader<Landed>,
+    mut shake: ResMut<CameraShake>,
 ) {
-    let Ok(mut ortho) = camera_query.get_single_mut() else {
+    for landed in landed_events.iter() {
+        if !active_effects.contains(&Effect::ViolentLandings)
+            || landed.fall_speed < MIN_LANDING_SHAKE_FALL_SPEED
+        {
+            continue;
+        }
+        let strength = (landed.fall_speed / MAX_LANDING_SHAKE_FALL_SPEED).min(1.);
+        shake.sustain_trauma(strength * MAX_LANDING_TRAUMA);
+    }
+}
+
+fn change_gravity(active_effects: Res<ActiveEffects>, mut gravity_query: Query<&mut GravityScale>) {
+    if !active_effects.is_changed() {
+        return;
+    }
+    let Ok(mut gravity) = gravity_query.get_single_mut() else {
         return;
     };
-    if let Some(Effect::Earthquake) = effect_q.last() {
-        let dz = 0.025 - random::<f32>() * 0.05;
-        // Do not move the camera too much, clamp the value
-        ortho.scale = f32::clamp(ortho.scale - dz, 0.975, 1.);
+    // High and low gravity conflict, so at most one of them is active
+    gravity.0 = if active_effects.contains(&Effect::HighGravity) {
+        8.0
+    } else if active_effects.contains(&Effect::LowGravity) {
+        2.0
     } else {
-        ortho.scale = 1.;
+        4.0
     }
 }
 
-fn change_gravity(effect_q: Res<EffectQueue>, mut gravity_query: Query<&mut GravityScale>) {
-    if !effect_q.is_changed() {
+fn change_time_scale(
+    active_effects: Res<ActiveEffects>,
+    effects: Res<TimeScaleEffects>,
+    mut time_scale: ResMut<TimeScale>,
+) {
+    if !active_effects.is_changed() {
         return;
     }
-    let Ok(mut gravity) = gravity_query.get_single_mut() else {
+    let mut new_scale = TimeScale::default();
+    for effect in active_effects.iter() {
+        let time_effect = match effect {
+            Effect::SlowMo => effects.slow_mo,
+            Effect::FastMo => effects.fast_mo,
+            _ => continue,
+        };
+        new_scale.game *= time_effect.speed;
+        if time_effect.scales_wall_timer {
+            new_scale.wall_timer *= time_effect.speed;
+        }
+    }
+    time_scale.set_if_neq(new_scale);
+}
+
+fn reset_time_scale(mut time_scale: ResMut<TimeScale>) {
+    time_scale.set_if_neq(TimeScale::default());
+}
+
+fn apply_time_scale(time_scale: Res<TimeScale>, mut time: ResMut<Time>) {
+    if time_scale.is_changed() {
+        // The fixed timestep follows the relative speed, and so do Rapier and the gameplay
+        time.set_relative_speed(time_scale.game);
+    }
+}
+
+fn change_platform_speed(
+    active_effects: Res<ActiveEffects>,
+    mut platform_speed: ResMut<PlatformSpeed>,
+) {
+    if !active_effects.is_changed() {
         return;
-    };
-    gravity.0 = match effect_q.last() {
-        Some(Effect::HighGravity) => 8.0,
-        Some(Effect::LowGravity) => 2.0,
-        _ => 4.0,
+    }
+    let speed: f32 = active_effects
+        .iter()
+        .map(|effect| match effect {
+            Effect::FastPlatforms => 2.0,
+            Effect::SlowPlatforms => 0.5,
+            _ => 1.0,
+        })
+        .product();
+    if platform_speed.effects != speed {
+        platform_speed.effects = speed;
     }
 }
 
@@ -125,25 +366,23 @@ fn change_gravity(effect_q: Res<EffectQueue>, mut gravity_query: Query<&mut Grav
 pub struct Darkness;
 
 fn apply_darkness(
-    effect_q: Res<EffectQueue>,
-    window_query: Query<&Window, With<PrimaryWindow>>,
+    active_effects: Res<ActiveEffects>,
+    arena: Res<Arena>,
+    darkness_query: Query<(), With<Darkness>>,
     mut commands: Commands,
 ) {
-    if !effect_q.is_changed() {
+    if !active_effects.is_changed() || !darkness_query.is_empty() {
         return;
     }
-    let Ok(window) = window_query.get_single() else {
-        return;
-    };
-    if let Some(Effect::Darkness) = effect_q.last() {
+    if active_effects.contains(&Effect::Darkness) {
         commands.spawn((
             SpriteBundle {
                 sprite: Sprite {
                     color: Color::rgba(0.0, 0.0, 0.0, 0.975),
-                    custom_size: Some(Vec2::new(window.width() * 2., window.height() * 2.)),
+                    custom_size: Some(Vec2::new(arena.width * 2., arena.height * 2.)),
                     ..default()
                 },
-                transform: Transform::from_xyz(window.height() / 2., window.width() / 2., 999.),
+                transform: Transform::from_xyz(arena.height / 2., arena.width / 2., 999.),
                 ..default()
             },
             Darkness,
@@ -152,14 +391,11 @@ fn apply_darkness(
 }
 
 fn remove_darkness(
-    effect_q: Res<EffectQueue>,
+    active_effects: Res<ActiveEffects>,
     mut commands: Commands,
     darkness_query: Query<Entity, With<Darkness>>,
 ) {
-    if !effect_q.is_changed() {
-        return;
-    }
-    if let Some(Effect::Darkness) = effect_q.last() {
+    if !active_effects.is_changed() || active_effects.contains(&Effect::Darkness) {
         return;
     }
     for entity in darkness_query.iter() {
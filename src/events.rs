@@ -1,10 +1,24 @@
-use bevy::{prelude::*, utils::Instant};
+use std::{
+    ops::{Deref, DerefMut},
+    time::Duration,
+};
 
-use crate::{player::Player, AppState, Score, Wall};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
-pub const MAX_TIME_TO_REACH_WALL: f32 = 15.0;
+use crate::{
+    difficulty::{choose_difficulty, Difficulty},
+    effects::TimeScale,
+    player::Player,
+    recording::Playback,
+    settings::WallTimerMode,
+    AppState, GameplaySet, Score, Wall,
+};
 
-#[derive(Resource, Clone, Copy)]
+/// How long the timer stays at zero before the run is lost.
+const OUT_OF_TIME_GRACE_SECS: f32 = 0.5;
+
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Reason {
     Died,
     OutOfTime,
@@ -12,35 +26,144 @@ pub enum Reason {
 pub struct WallReached(pub Wall);
 
 pub struct Lost(pub Reason);
-#[derive(Resource, Deref, DerefMut)]
-pub struct GameTimer(pub Instant);
+
+/// Sent when the player lands on a platform.
+pub struct Landed {
+    /// The downwards speed of the player right before landing.
+    pub fall_speed: f32,
+}
+
+/// Sent when time is added to the bank timer, with the added seconds.
+pub struct TimeAdded(pub f32);
+
+/// Countdown to reach the next wall, ticked on every fixed step so it follows
+/// the game clock: it stops while paused and can be driven step by step.
+#[derive(Resource)]
+pub struct GameTimer {
+    pub timer: Timer,
+    /// How the timer is refilled at the walls, fixed for the whole run.
+    pub mode: WallTimerMode,
+}
+
+impl Deref for GameTimer {
+    type Target = Timer;
+
+    fn deref(&self) -> &Timer {
+        &self.timer
+    }
+}
+
+impl DerefMut for GameTimer {
+    fn deref_mut(&mut self) -> &mut Timer {
+        &mut self.timer
+    }
+}
+
+impl GameTimer {
+    pub fn new(mode: WallTimerMode, secs: f32) -> Self {
+        GameTimer {
+            timer: Timer::from_seconds(secs + OUT_OF_TIME_GRACE_SECS, TimerMode::Once),
+            mode,
+        }
+    }
+
+    /// Seconds left to reach the wall, as shown to the player.
+    pub fn time_left_secs(&self) -> f32 {
+        (self.remaining_secs() - OUT_OF_TIME_GRACE_SECS).max(0.)
+    }
+}
 pub struct EventPlugin;
 
 impl Plugin for EventPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<WallReached>()
             .add_event::<Lost>()
-            .add_system(initialize_game_timer.in_schedule(OnEnter(AppState::InGame)))
+            .add_event::<Landed>()
+            .add_event::<TimeAdded>()
+            // Also given by the save, this is the default when running headless
+            .init_resource::<WallTimerMode>()
+            .add_system(reset_score.in_schedule(OnEnter(AppState::InGame)))
+            .add_system(
+                initialize_game_timer
+                    .after(choose_difficulty)
+                    .in_schedule(OnEnter(AppState::InGame)),
+            )
             .add_systems(
                 (
-                    transition_to_restart,
-                    update_score,
-                    reset_game_timer,
+                    tick_game_timer.before(is_out_of_time),
                     is_dead,
                     is_out_of_time,
                 )
-                    .in_set(OnUpdate(AppState::InGame)),
+                    .in_set(GameplaySet::Simulation)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_systems(
+                (
+                    transition_to_restart,
+                    update_score,
+                    refill_game_timer.after(update_score),
+                )
+                    .in_set(GameplaySet::Events)
+                    .in_schedule(CoreSchedule::FixedUpdate),
             );
     }
 }
 
-fn initialize_game_timer(mut commands: Commands) {
-    commands.insert_resource(GameTimer(Instant::now()));
+fn reset_score(mut commands: Commands) {
+    commands.insert_resource(Score(0));
 }
 
-fn reset_game_timer(mut timer: ResMut<GameTimer>, mut event: EventReader<WallReached>) {
-    if event.iter().next().is_some() {
-        timer.0 = Instant::now();
+fn initialize_game_timer(
+    mut commands: Commands,
+    wall_timer_mode: Res<WallTimerMode>,
+    difficulty: Res<Difficulty>,
+    playback: Option<Res<Playback>>,
+) {
+    // A replay keeps the timer mode it was recorded with
+    let mode = playback.map_or(*wall_timer_mode, |playback| playback.timer_mode);
+    commands.insert_resource(GameTimer::new(
+        mode,
+        difficulty.curves.time_to_reach_wall.at(0),
+    ));
+}
+
+fn tick_game_timer(
+    fixed_time: Res<FixedTime>,
+    time_scale: Res<TimeScale>,
+    mut timer: ResMut<GameTimer>,
+) {
+    // Steps follow the game speed, while the wall timer ignores it
+    // unless the active effects say otherwise
+    timer.tick(
+        fixed_time
+            .period
+            .mul_f32(time_scale.wall_timer / time_scale.game),
+    );
+}
+
+fn refill_game_timer(
+    mut timer: ResMut<GameTimer>,
+    difficulty: Res<Difficulty>,
+    score: Res<Score>,
+    mut event: EventReader<WallReached>,
+    mut time_added: EventWriter<TimeAdded>,
+) {
+    if event.iter().next().is_none() {
+        return;
+    }
+    match timer.mode {
+        WallTimerMode::PerWall => {
+            let secs = difficulty.curves.time_to_reach_wall.at(score.0);
+            timer.set_duration(Duration::from_secs_f32(secs + OUT_OF_TIME_GRACE_SECS));
+            timer.reset();
+        }
+        WallTimerMode::Bank => {
+            // What is left carries over to the next wall
+            let bonus = difficulty.curves.time_bonus.at(score.0);
+            let duration = timer.duration() + Duration::from_secs_f32(bonus);
+            timer.set_duration(duration);
+            time_added.send(TimeAdded(bonus));
+        }
     }
 }
 
@@ -62,7 +185,7 @@ fn update_score(mut score: ResMut<Score>, mut event: EventReader<WallReached>) {
 }
 
 fn is_out_of_time(timer: Res<GameTimer>, mut event_writer: EventWriter<Lost>) {
-    if timer.0.elapsed().as_secs_f32() > MAX_TIME_TO_REACH_WALL + 0.5 {
+    if timer.finished() {
         event_writer.send(Lost(Reason::OutOfTime));
     }
 }
@@ -0,0 +1,421 @@
+use std::{fmt, fs, io, path::Path};
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::PhysicsSet;
+
+use crate::{
+    animation::Animator,
+    binary::{self, Reader, Truncated},
+    physics::{record_simulated_transforms, Interpolated},
+    player::{Facing, Player},
+    recording::Playback,
+    rng::RunSeed,
+    save::data_path,
+    AppState, HighScore, Score,
+};
+
+/// Where the path of the personal best is saved, inside the data directory.
+const BEST_GHOST_PATH: &str = "replays/best.ssgh";
+
+const MAGIC: &[u8; 4] = b"SSGH";
+const VERSION: u8 = 1;
+/// Bytes taken by a [`GhostFrame`].
+const FRAME_SIZE: usize = 11;
+
+/// Animation clips of the player, in the order they are saved in.
+const CLIPS: [&str; 3] = ["idle", "run", "jump"];
+
+const GHOST_ALPHA: f32 = 0.4;
+
+/// Where the player was, and how it looked, at one gameplay step.
+#[derive(Debug, Clone, Copy)]
+pub struct GhostFrame {
+    pub position: Vec2,
+    pub facing: Facing,
+    /// Index of the animation clip in [`CLIPS`].
+    pub clip: u8,
+    pub sprite_index: u8,
+}
+
+/// The path of a whole run.
+#[derive(Debug, Clone)]
+pub struct GhostRun {
+    pub seed: RunSeed,
+    pub score: usize,
+    pub frames: Vec<GhostFrame>,
+}
+
+#[derive(Debug)]
+pub enum GhostError {
+    Io(io::Error),
+    NotAGhost,
+    UnsupportedVersion(u8),
+    Truncated,
+}
+
+impl fmt::Display for GhostError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GhostError::Io(err) => write!(f, "{err}"),
+            GhostError::NotAGhost => write!(f, "not a SideSwap ghost"),
+            GhostError::UnsupportedVersion(version) => {
+                write!(f, "unsupported ghost version {version}")
+            }
+            GhostError::Truncated => write!(f, "ghost is truncated"),
+        }
+    }
+}
+
+impl From<io::Error> for GhostError {
+    fn from(err: io::Error) -> Self {
+        GhostError::Io(err)
+    }
+}
+
+impl From<Truncated> for GhostError {
+    fn from(_: Truncated) -> Self {
+        GhostError::Truncated
+    }
+}
+
+impl GhostRun {
+    /// `"SSGH" | version: u8 | seed: u64 | score: u32 | frames: u32 | frame*`, where each
+    /// frame is `x: f32 | y: f32 | facing: u8 | clip: u8 | sprite index: u8`, in little endian.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = binary::header(MAGIC, VERSION);
+        bytes.extend_from_slice(&self.seed.0.to_le_bytes());
+        bytes.extend_from_slice(&(self.score as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        for frame in self.frames.iter() {
+            bytes.extend_from_slice(&frame.position.x.to_le_bytes());
+            bytes.extend_from_slice(&frame.position.y.to_le_bytes());
+            bytes.push(match frame.facing {
+                Facing::Right => 0,
+                Facing::Left => 1,
+            });
+            bytes.push(frame.clip);
+            bytes.push(frame.sprite_index);
+        }
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<GhostRun, GhostError> {
+        let mut reader = Reader::new(bytes);
+        if !reader.magic(MAGIC)? {
+            return Err(GhostError::NotAGhost);
+        }
+        let version = reader.u8()?;
+        if version != VERSION {
+            return Err(GhostError::UnsupportedVersion(version));
+        }
+        let seed = RunSeed(reader.u64()?);
+        let score = reader.u32()? as usize;
+        let frame_count = reader.u32()?;
+
+        // The count is only trusted as far as there are bytes for its frames
+        let mut frames =
+            Vec::with_capacity((frame_count as usize).min(reader.remaining() / FRAME_SIZE));
+        for _ in 0..frame_count {
+            let position = Vec2::new(reader.f32()?, reader.f32()?);
+            let facing = if reader.u8()? == 0 {
+                Facing::Right
+            } else {
+                Facing::Left
+            };
+            frames.push(GhostFrame {
+                position,
+                facing,
+                clip: reader.u8()?,
+                sprite_index: reader.u8()?,
+            });
+        }
+
+        Ok(GhostRun {
+            seed,
+            score,
+            frames,
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), GhostError> {
+        binary::save(path.as_ref(), &self.encode())?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<GhostRun, GhostError> {
+        GhostRun::decode(&fs::read(path)?)
+    }
+}
+
+/// The path of the personal best, if there is one.
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct BestGhost(pub Option<GhostRun>);
+
+/// The path of the current run so far.
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct GhostTrail(pub Vec<GhostFrame>);
+
+/// The high score as it stood when the current run started, since the high score is
+/// raised during the run.
+#[derive(Resource, Default)]
+pub struct HighScoreBeforeRun(pub usize);
+
+/// A semi-transparent copy of the player, following the path of the personal best.
+#[derive(Component)]
+pub struct Ghost;
+
+pub struct GhostPlugin;
+
+impl Plugin for GhostPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BestGhost>()
+            .init_resource::<GhostTrail>()
+            .init_resource::<HighScoreBeforeRun>()
+            .add_startup_system(load_best_ghost)
+            .add_systems(
+                (clear_ghost_trail, remember_high_score).in_schedule(OnEnter(AppState::InGame)),
+            )
+            .add_system(spawn_ghost.in_set(OnUpdate(AppState::InGame)))
+            .add_systems(
+                (record_ghost_trail, move_ghost)
+                    .chain()
+                    .after(PhysicsSet::Writeback)
+                    .before(record_simulated_transforms)
+                    .distributive_run_if(in_state(AppState::InGame))
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_systems((save_best_ghost, despawn_ghost).in_schedule(OnExit(AppState::InGame)));
+    }
+}
+
+fn load_best_ghost(mut best_ghost: ResMut<BestGhost>) {
+    let path = data_path(BEST_GHOST_PATH);
+    match GhostRun::load(&path) {
+        Ok(ghost) => best_ghost.0 = Some(ghost),
+        Err(GhostError::Io(err)) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => warn!("Could not load the ghost from {}: {err}", path.display()),
+    }
+}
+
+fn clear_ghost_trail(mut trail: ResMut<GhostTrail>) {
+    trail.clear();
+}
+
+fn remember_high_score(
+    high_score: Option<Res<HighScore>>,
+    mut high_score_before_run: ResMut<HighScoreBeforeRun>,
+) {
+    high_score_before_run.0 = high_score.map_or(0, |high_score| high_score.0);
+}
+
+fn spawn_ghost(
+    mut commands: Commands,
+    seed: Res<RunSeed>,
+    best_ghost: Res<BestGhost>,
+    player_query: Query<&Animator, With<Player>>,
+    ghost_query: Query<(), With<Ghost>>,
+) {
+    if !seed.is_changed() || !ghost_query.is_empty() {
+        return;
+    }
+    let (Some(ghost), Ok(player_animator)) = (best_ghost.0.as_ref(), player_query.get_single())
+    else {
+        return;
+    };
+    // Only chase the personal best on the seed it was set on
+    if ghost.seed != *seed {
+        return;
+    }
+    let Some(first) = ghost.frames.first() else {
+        return;
+    };
+    // The ghost looks like the player, and only shows the frames of its run
+    let mut animator = Animator::new(player_animator.clips().clone(), CLIPS[0]);
+    animator.paused = true;
+    commands.spawn((
+        Ghost,
+        Interpolated::default(),
+        animator,
+        SpriteSheetBundle {
+            sprite: TextureAtlasSprite {
+                color: Color::rgba(1., 1., 1., GHOST_ALPHA),
+                ..default()
+            },
+            transform: Transform::from_translation(first.position.extend(498.)),
+            ..default()
+        },
+    ));
+}
+
+fn record_ghost_trail(
+    mut trail: ResMut<GhostTrail>,
+    player_query: Query<(&Transform, &Facing, &Animator), With<Player>>,
+) {
+    let Ok((transform, facing, animator)) = player_query.get_single() else {
+        return;
+    };
+    let clip = CLIPS
+        .iter()
+        .position(|clip| *clip == animator.current())
+        .unwrap_or_default();
+    trail.push(GhostFrame {
+        position: transform.translation.truncate(),
+        facing: *facing,
+        clip: clip as u8,
+        sprite_index: animator.frame() as u8,
+    });
+}
+
+fn move_ghost(
+    best_ghost: Res<BestGhost>,
+    trail: Res<GhostTrail>,
+    mut ghost_query: Query<
+        (
+            &mut Transform,
+            &mut Animator,
+            &mut TextureAtlasSprite,
+            &mut Visibility,
+        ),
+        With<Ghost>,
+    >,
+) {
+    let Some(run) = best_ghost.0.as_ref() else {
+        return;
+    };
+    // The ghost is as many steps into its run as the player is
+    let step = trail.len().saturating_sub(1);
+    for (mut transform, mut animator, mut sprite, mut visibility) in ghost_query.iter_mut() {
+        // The ghost vanishes once its run is over
+        let Some(frame) = run.frames.get(step) else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+        transform.translation.x = frame.position.x;
+        transform.translation.y = frame.position.y;
+        let clip = CLIPS.get(frame.clip as usize).unwrap_or(&CLIPS[0]);
+        animator.play(clip);
+        animator.seek(frame.sprite_index as usize);
+        sprite.flip_x = matches!(frame.facing, Facing::Left);
+    }
+}
+
+fn save_best_ghost(
+    score: Res<Score>,
+    seed: Res<RunSeed>,
+    playback: Option<Res<Playback>>,
+    high_score_before_run: Res<HighScoreBeforeRun>,
+    mut trail: ResMut<GhostTrail>,
+    mut best_ghost: ResMut<BestGhost>,
+) {
+    // Only a new personal best replaces the ghost, even when there is no ghost yet
+    if playback.is_some() || score.0 <= high_score_before_run.0 {
+        return;
+    }
+    let ghost = GhostRun {
+        seed: *seed,
+        score: score.0,
+        frames: std::mem::take(&mut trail.0),
+    };
+    let path = data_path(BEST_GHOST_PATH);
+    if let Err(err) = ghost.save(&path) {
+        warn!("Could not save the ghost to {}: {err}", path.display());
+    }
+    best_ghost.0 = Some(ghost);
+}
+
+fn despawn_ghost(mut commands: Commands, query: Query<Entity, With<Ghost>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ghost_run() -> GhostRun {
+        GhostRun {
+            seed: RunSeed(0xDEAD_BEEF),
+            score: 7,
+            frames: (0..3)
+                .map(|i| GhostFrame {
+                    position: Vec2::new(i as f32 * 1.5, -(i as f32)),
+                    facing: if i % 2 == 0 {
+                        Facing::Right
+                    } else {
+                        Facing::Left
+                    },
+                    clip: i,
+                    sprite_index: 10 + i,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let run = ghost_run();
+        let decoded = GhostRun::decode(&run.encode()).unwrap();
+        assert_eq!(decoded.seed, run.seed);
+        assert_eq!(decoded.score, run.score);
+        assert_eq!(decoded.frames.len(), run.frames.len());
+        for (decoded, frame) in decoded.frames.iter().zip(run.frames.iter()) {
+            assert_eq!(decoded.position, frame.position);
+            assert_eq!(
+                matches!(decoded.facing, Facing::Left),
+                matches!(frame.facing, Facing::Left)
+            );
+            assert_eq!(decoded.clip, frame.clip);
+            assert_eq!(decoded.sprite_index, frame.sprite_index);
+        }
+    }
+
+    #[test]
+    fn encodes_frames_in_their_size() {
+        let run = ghost_run();
+        let empty = GhostRun {
+            frames: vec![],
+            ..run.clone()
+        };
+        assert_eq!(
+            run.encode().len() - empty.encode().len(),
+            run.frames.len() * FRAME_SIZE
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_ghosts() {
+        let bytes = ghost_run().encode();
+        for length in 0..bytes.len() {
+            assert!(matches!(
+                GhostRun::decode(&bytes[..length]),
+                Err(GhostError::Truncated)
+            ));
+        }
+    }
+
+    #[test]
+    fn rejects_frame_counts_past_the_end() {
+        let mut bytes = ghost_run().encode();
+        // The frame count, right after the magic, version, seed and score
+        bytes[17..21].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            GhostRun::decode(&bytes),
+            Err(GhostError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let mut bytes = ghost_run().encode();
+        bytes[4] = VERSION + 1;
+        assert!(matches!(
+            GhostRun::decode(&bytes),
+            Err(GhostError::UnsupportedVersion(_))
+        ));
+        assert!(matches!(
+            GhostRun::decode(b"SSRP\x03"),
+            Err(GhostError::NotAGhost)
+        ));
+    }
+}
@@ -0,0 +1,295 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    difficulty::{Difficulty, DifficultyLevel},
+    effects::{ActiveEffects, Effect},
+    events::{GameTimer, Reason},
+    recording::{save_recording, Playback},
+    rng::RunSeed,
+    settings::WallTimerMode,
+    AppState, GameMode, Score,
+};
+
+/// How many runs the leaderboard keeps.
+pub const LEADERBOARD_SIZE: usize = 10;
+pub const MAX_NAME_LENGTH: usize = 12;
+/// Name of entries left without one.
+const ANONYMOUS_NAME: &str = "???";
+
+/// A finished run on the leaderboard.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LeaderboardEntry {
+    pub name: String,
+    pub score: usize,
+    /// When the run ended, in seconds since the Unix epoch.
+    pub date: u64,
+    pub reason: Reason,
+    pub seed: RunSeed,
+    pub mode: GameMode,
+    #[serde(default)]
+    pub timer_mode: WallTimerMode,
+    #[serde(default)]
+    pub difficulty: DifficultyLevel,
+    /// Every effect drawn during the run, in order.
+    pub effects: Vec<Effect>,
+}
+
+impl LeaderboardEntry {
+    /// The date of the run as `YYYY-MM-DD`, in UTC.
+    pub fn date_string(&self) -> String {
+        // Civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+        let days = (self.date / 86_400) as i64 + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+        let month = if month_index < 10 {
+            month_index + 3
+        } else {
+            month_index - 9
+        };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+        format!("{year:04}-{month:02}-{day:02}")
+    }
+}
+
+/// The best runs, highest score first.
+#[derive(Resource, Debug, Default, Clone, Deref, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Leaderboard(Vec<LeaderboardEntry>);
+
+impl Leaderboard {
+    /// Whether a run with this score would make it onto the leaderboard.
+    pub fn qualifies(&self, score: usize) -> bool {
+        score > 0
+            && (self.0.len() < LEADERBOARD_SIZE
+                || self.0.last().map_or(true, |entry| score > entry.score))
+    }
+
+    /// Adds the entry below any run with the same score, returning its rank from 0.
+    pub fn insert(&mut self, entry: LeaderboardEntry) -> Option<usize> {
+        let rank = self
+            .0
+            .iter()
+            .position(|other| other.score < entry.score)
+            .unwrap_or(self.0.len());
+        self.0.insert(rank, entry);
+        self.0.truncate(LEADERBOARD_SIZE);
+        (rank < LEADERBOARD_SIZE).then_some(rank)
+    }
+}
+
+/// A run good enough for the leaderboard, waiting for the player to enter a name.
+#[derive(Resource, Debug, Deref, DerefMut)]
+pub struct PendingEntry(pub LeaderboardEntry);
+
+/// Effects drawn so far during the current run.
+#[derive(Resource, Debug, Default, Deref, DerefMut)]
+pub struct RunEffects(pub Vec<Effect>);
+
+/// Sent to add the pending entry to the leaderboard under the name typed so far.
+pub struct SubmitEntry;
+
+/// Sent once the pending entry made it onto the leaderboard, with its rank from 0.
+pub struct EntrySubmitted(pub usize);
+
+pub struct LeaderboardPlugin;
+
+impl Plugin for LeaderboardPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Leaderboard>()
+            .init_resource::<RunEffects>()
+            .add_event::<SubmitEntry>()
+            .add_event::<EntrySubmitted>()
+            .add_system(clear_run_effects.in_schedule(OnEnter(AppState::InGame)))
+            .add_system(track_run_effects.in_set(OnUpdate(AppState::InGame)))
+            .add_system(
+                create_pending_entry
+                    .before(save_recording)
+                    .in_schedule(OnExit(AppState::InGame)),
+            )
+            .add_system(
+                submit_pending_entry
+                    .run_if(on_event::<SubmitEntry>())
+                    .in_set(OnUpdate(AppState::YouDied)),
+            )
+            // Leaving the screen keeps the run, even without a name
+            .add_system(submit_pending_entry.in_schedule(OnExit(AppState::YouDied)));
+    }
+}
+
+fn clear_run_effects(mut run_effects: ResMut<RunEffects>) {
+    run_effects.clear();
+}
+
+fn track_run_effects(active_effects: Res<ActiveEffects>, mut run_effects: ResMut<RunEffects>) {
+    if !active_effects.is_changed() {
+        return;
+    }
+    if let Some(effect) = active_effects.last() {
+        if run_effects.last() != Some(effect) {
+            run_effects.push(*effect);
+        }
+    }
+}
+
+fn create_pending_entry(
+    mut commands: Commands,
+    state: Res<State<AppState>>,
+    playback: Option<Res<Playback>>,
+    leaderboard: Res<Leaderboard>,
+    score: Res<Score>,
+    reason: Option<Res<Reason>>,
+    seed: Res<RunSeed>,
+    game_mode: Res<GameMode>,
+    timer: Res<GameTimer>,
+    difficulty: Res<Difficulty>,
+    mut run_effects: ResMut<RunEffects>,
+) {
+    // The state already holds the one being entered, runs quit from the menu don't count
+    if state.0 != AppState::YouDied || playback.is_some() || !leaderboard.qualifies(score.0) {
+        return;
+    }
+    let Some(reason) = reason else {
+        return;
+    };
+    let date = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    commands.insert_resource(PendingEntry(LeaderboardEntry {
+        name: String::new(),
+        score: score.0,
+        date,
+        reason: *reason,
+        seed: *seed,
+        mode: *game_mode,
+        timer_mode: timer.mode,
+        difficulty: difficulty.level,
+        effects: std::mem::take(&mut run_effects.0),
+    }));
+}
+
+fn submit_pending_entry(
+    mut commands: Commands,
+    pending: Option<Res<PendingEntry>>,
+    mut leaderboard: ResMut<Leaderboard>,
+    mut submitted: EventWriter<EntrySubmitted>,
+) {
+    let Some(pending) = pending else {
+        return;
+    };
+    commands.remove_resource::<PendingEntry>();
+    let mut entry = pending.0.clone();
+    let name = entry.name.trim();
+    entry.name = if name.is_empty() {
+        ANONYMOUS_NAME.to_string()
+    } else {
+        name.to_string()
+    };
+    if let Some(rank) = leaderboard.insert(entry) {
+        submitted.send(EntrySubmitted(rank));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, score: usize) -> LeaderboardEntry {
+        LeaderboardEntry {
+            name: name.to_string(),
+            score,
+            date: 0,
+            reason: Reason::Died,
+            seed: RunSeed(0),
+            mode: GameMode::default(),
+            timer_mode: WallTimerMode::default(),
+            difficulty: DifficultyLevel::default(),
+            effects: Vec::new(),
+        }
+    }
+
+    fn full_board() -> Leaderboard {
+        let mut leaderboard = Leaderboard::default();
+        for score in (1..=LEADERBOARD_SIZE).rev() {
+            leaderboard.insert(entry("full", score * 10));
+        }
+        leaderboard
+    }
+
+    fn names(leaderboard: &Leaderboard) -> Vec<&str> {
+        leaderboard
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn inserts_ties_below_equal_scores() {
+        let mut leaderboard = Leaderboard::default();
+        assert_eq!(leaderboard.insert(entry("first", 5)), Some(0));
+        assert_eq!(leaderboard.insert(entry("better", 8)), Some(0));
+        assert_eq!(leaderboard.insert(entry("second", 5)), Some(2));
+        assert_eq!(leaderboard.insert(entry("worse", 2)), Some(3));
+        assert_eq!(names(&leaderboard), ["better", "first", "second", "worse"]);
+    }
+
+    #[test]
+    fn truncates_to_the_leaderboard_size() {
+        let mut leaderboard = full_board();
+        assert_eq!(leaderboard.len(), LEADERBOARD_SIZE);
+
+        assert_eq!(leaderboard.insert(entry("top", 1_000)), Some(0));
+        assert_eq!(leaderboard.len(), LEADERBOARD_SIZE);
+        assert_eq!(leaderboard.last().unwrap().score, 20);
+
+        let lowest = leaderboard.last().unwrap().score;
+        assert_eq!(leaderboard.insert(entry("tied", lowest)), None);
+        assert_eq!(leaderboard.insert(entry("low", 1)), None);
+        assert_eq!(leaderboard.len(), LEADERBOARD_SIZE);
+        assert!(!names(&leaderboard).contains(&"tied"));
+        assert!(!names(&leaderboard).contains(&"low"));
+    }
+
+    #[test]
+    fn qualifies_on_a_full_board_only_above_the_lowest_score() {
+        let leaderboard = full_board();
+        let lowest = leaderboard.last().unwrap().score;
+        assert!(!leaderboard.qualifies(lowest - 1));
+        assert!(!leaderboard.qualifies(lowest));
+        assert!(leaderboard.qualifies(lowest + 1));
+        assert!(leaderboard.qualifies(1_000));
+    }
+
+    #[test]
+    fn score_zero_never_qualifies() {
+        assert!(!Leaderboard::default().qualifies(0));
+        assert!(!full_board().qualifies(0));
+        assert!(Leaderboard::default().qualifies(1));
+    }
+
+    #[test]
+    fn formats_dates_in_utc() {
+        for (date, expected) in [
+            (0, "1970-01-01"),
+            (86_399, "1970-01-01"),
+            (951_782_400, "2000-02-29"),
+            (1_000_000_000, "2001-09-09"),
+            (1_709_164_800, "2024-02-29"),
+            (1_709_251_199, "2024-02-29"),
+            (1_709_251_200, "2024-03-01"),
+        ] {
+            let entry = LeaderboardEntry {
+                date,
+                ..entry("date", 1)
+            };
+            assert_eq!(entry.date_string(), expected, "date {date}");
+        }
+    }
+}
@@ -0,0 +1,144 @@
+#![allow(clippy::type_complexity, clippy::too_many_arguments)]
+
+use actions::ActionPlugin;
+use animation::AnimatorPlugin;
+use bevy::{app::PluginGroupBuilder, prelude::*};
+use difficulty::DifficultyPlugin;
+use effects::EffectsPlugin;
+use events::EventPlugin;
+use ghost::GhostPlugin;
+use leaderboard::LeaderboardPlugin;
+use pause::PausePlugin;
+use physics::{Arena, PhysicsPlugin};
+use player::PlayerPlugin;
+use recording::RecordingPlugin;
+use rng::RngPlugin;
+use save::SavePlugin;
+use serde::{Deserialize, Serialize};
+use shake::{ShakeOrigin, ShakePlugin};
+use sprites::SpritesPlugin;
+use tiles::TilesPlugin;
+use ui::UIPlugin;
+
+pub mod actions;
+pub mod animation;
+mod binary;
+pub mod difficulty;
+pub mod effects;
+pub mod events;
+pub mod ghost;
+pub mod leaderboard;
+pub mod pause;
+pub mod physics;
+pub mod player;
+mod reachability;
+pub mod recording;
+pub mod rng;
+pub mod save;
+pub mod settings;
+pub mod shake;
+pub mod sprites;
+pub mod tiles;
+pub mod ui;
+
+#[derive(Component, Clone, Copy, PartialEq)]
+pub enum Wall {
+    Left,
+    Right,
+}
+
+#[derive(Resource)]
+pub struct Score(pub usize);
+#[derive(Resource)]
+pub struct HighScore(pub usize);
+
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameMode {
+    #[default]
+    Normal,
+    /// Side effects stack on top of each other instead of replacing one another.
+    Hard,
+}
+
+impl GameMode {
+    pub fn next(&self) -> GameMode {
+        match self {
+            GameMode::Normal => GameMode::Hard,
+            GameMode::Hard => GameMode::Normal,
+        }
+    }
+}
+
+/// Every plugin of the game, in the order they depend on each other. Add it after
+/// `DefaultPlugins`, or disable the input, pause, UI, save and replay plugins to run the
+/// gameplay headless under `MinimalPlugins`.
+pub struct SideSwapPlugins;
+
+impl PluginGroup for SideSwapPlugins {
+    fn build(self) -> PluginGroupBuilder {
+        PluginGroupBuilder::start::<Self>()
+            .add(CorePlugin)
+            .add(SavePlugin)
+            .add(DifficultyPlugin)
+            .add(SpritesPlugin)
+            .add(ActionPlugin)
+            .add(PausePlugin)
+            .add(PhysicsPlugin)
+            .add(RngPlugin)
+            .add(UIPlugin)
+            .add(EffectsPlugin)
+            .add(EventPlugin)
+            .add(AnimatorPlugin)
+            .add(TilesPlugin)
+            .add(PlayerPlugin)
+            .add(RecordingPlugin)
+            .add(GhostPlugin)
+            .add(LeaderboardPlugin)
+            .add(ShakePlugin)
+    }
+}
+
+/// The states of the game, its mode and the camera.
+pub struct CorePlugin;
+
+impl Plugin for CorePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ClearColor(Color::rgb_u8(166, 234, 255)))
+            .init_resource::<GameMode>()
+            .add_state::<AppState>()
+            .add_startup_system(spawn_camera);
+    }
+}
+
+fn spawn_camera(mut commands: Commands, arena: Res<Arena>) {
+    let origin = Vec3::new(arena.width / 2.0, arena.height / 2.0, 1000.);
+    commands.spawn((
+        Camera2dBundle {
+            transform: Transform::from_translation(origin),
+            ..default()
+        },
+        ShakeOrigin(origin),
+    ));
+}
+
+/// Gameplay systems, run in order on the fixed timestep right before the physics step.
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GameplaySet {
+    /// Reading the input of the player, from the keyboard or a recording.
+    Input,
+    /// Moving the player and the platforms and sending the gameplay events.
+    Simulation,
+    /// Reacting to the events sent during the simulation.
+    Events,
+}
+
+#[derive(States, Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum AppState {
+    #[default]
+    MainMenu,
+    InGame,
+    YouDied,
+    Leaderboard,
+    Controls,
+    Settings,
+}
@@ -0,0 +1,66 @@
+use bevy::{prelude::*, window::WindowFocused};
+
+use crate::{actions::Action, AppState};
+
+/// Whether the current run is paused, only meaningful while [`AppState::InGame`].
+/// Pausing stops the game clock, so no fixed step runs: Rapier, the platforms,
+/// the side effects and the wall timer all stay where they are.
+#[derive(States, Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum PauseState {
+    #[default]
+    Running,
+    Paused,
+}
+
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state::<PauseState>()
+            .add_systems((toggle_pause, pause_on_focus_lost).in_set(OnUpdate(AppState::InGame)))
+            .add_system(stop_clock.in_schedule(OnEnter(PauseState::Paused)))
+            .add_system(start_clock.in_schedule(OnExit(PauseState::Paused)))
+            .add_system(resume.in_schedule(OnExit(AppState::InGame)));
+    }
+}
+
+fn toggle_pause(
+    actions: Res<Input<Action>>,
+    pause_state: Res<State<PauseState>>,
+    mut next_pause_state: ResMut<NextState<PauseState>>,
+) {
+    if !actions.any_just_pressed([Action::Pause, Action::Back]) {
+        return;
+    }
+    next_pause_state.set(match pause_state.0 {
+        PauseState::Running => PauseState::Paused,
+        PauseState::Paused => PauseState::Running,
+    });
+}
+
+fn pause_on_focus_lost(
+    mut window_focused: EventReader<WindowFocused>,
+    mut next_pause_state: ResMut<NextState<PauseState>>,
+) {
+    if window_focused.iter().any(|event| !event.focused) {
+        next_pause_state.set(PauseState::Paused);
+    }
+}
+
+fn stop_clock(mut time: ResMut<Time>) {
+    time.pause();
+}
+
+fn start_clock(mut time: ResMut<Time>) {
+    time.unpause();
+}
+
+/// A run never starts paused, whichever way the last one was left.
+fn resume(
+    pause_state: Res<State<PauseState>>,
+    mut next_pause_state: ResMut<NextState<PauseState>>,
+) {
+    if pause_state.0 == PauseState::Paused {
+        next_pause_state.set(PauseState::Running);
+    }
+}
@@ -0,0 +1,160 @@
+use bevy::{ecs::schedule::ExecutorKind, prelude::*, transform::TransformSystem};
+use bevy_rapier2d::prelude::*;
+
+use crate::{AppState, GameplaySet};
+
+/// Length in seconds of a gameplay and physics step.
+pub const FIXED_TIMESTEP: f32 = 1. / 60.;
+
+/// Logical size of the play area, in pixels from its bottom left corner. The gameplay
+/// relies on it instead of the window, so that it runs the same with no window at all.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct Arena {
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Default for Arena {
+    fn default() -> Self {
+        Arena {
+            width: 640.,
+            height: 360.,
+        }
+    }
+}
+
+/// Draws an entity moved by the simulation between where it was at the last two fixed
+/// steps, so that it moves smoothly at any frame rate. Between steps its [`Transform`]
+/// holds the drawn position, and it is put back where the simulation left it before the
+/// next step.
+#[derive(Component, Debug, Default)]
+pub struct Interpolated {
+    previous: Option<Transform>,
+    current: Option<Transform>,
+}
+
+/// Runs the gameplay systems and Rapier on a fixed timestep, so that a run only depends
+/// on its seed and inputs and not on the frame rate.
+pub struct PhysicsPlugin;
+
+impl Plugin for PhysicsPlugin {
+    fn build(&self, app: &mut App) {
+        // Rapier places the colliders from their global transforms, which the default
+        // plugins propagate but the minimal ones used headless do not
+        if !app.is_plugin_added::<TransformPlugin>() {
+            app.add_plugin(TransformPlugin);
+        }
+        if !app.is_plugin_added::<HierarchyPlugin>() {
+            app.add_plugin(HierarchyPlugin);
+        }
+        app.init_resource::<Arena>()
+            .insert_resource(FixedTime::new_from_secs(FIXED_TIMESTEP))
+            .insert_resource(RapierConfiguration {
+                timestep_mode: TimestepMode::Fixed {
+                    dt: FIXED_TIMESTEP,
+                    substeps: 1,
+                },
+                ..default()
+            })
+            .add_plugin(
+                RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0)
+                    .with_default_system_setup(false),
+            )
+            .edit_schedule(CoreSchedule::FixedUpdate, |schedule| {
+                // Systems running one at a time always run in the same order,
+                // which keeps the simulation deterministic
+                schedule
+                    .set_executor_kind(ExecutorKind::SingleThreaded)
+                    .configure_sets(
+                        (
+                            GameplaySet::Input,
+                            GameplaySet::Simulation,
+                            GameplaySet::Events,
+                        )
+                            .chain()
+                            .before(PhysicsSet::SyncBackend),
+                    )
+                    .configure_set(GameplaySet::Input.run_if(in_state(AppState::InGame)))
+                    .configure_set(GameplaySet::Simulation.run_if(in_state(AppState::InGame)))
+                    .configure_set(GameplaySet::Events.run_if(in_state(AppState::InGame)))
+                    .configure_sets(
+                        (
+                            PhysicsSet::SyncBackend,
+                            PhysicsSet::SyncBackendFlush,
+                            PhysicsSet::StepSimulation,
+                            PhysicsSet::Writeback,
+                        )
+                            .chain(),
+                    );
+            })
+            .add_systems(
+                RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::SyncBackend)
+                    .in_base_set(PhysicsSet::SyncBackend)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_systems(
+                RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::SyncBackendFlush)
+                    .in_base_set(PhysicsSet::SyncBackendFlush)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_systems(
+                RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::StepSimulation)
+                    .in_base_set(PhysicsSet::StepSimulation)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_systems(
+                RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::Writeback)
+                    .in_base_set(PhysicsSet::Writeback)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(
+                record_simulated_transforms
+                    .after(PhysicsSet::Writeback)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(restore_simulated_transforms.in_base_set(CoreSet::PreUpdate))
+            .add_system(
+                interpolate_transforms
+                    .in_base_set(CoreSet::PostUpdate)
+                    .before(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+fn restore_simulated_transforms(
+    mut query: Query<(&mut Transform, &mut GlobalTransform, &Interpolated)>,
+) {
+    for (mut transform, mut global_transform, interpolated) in query.iter_mut() {
+        let Some(current) = interpolated.current else {
+            continue;
+        };
+        transform.set_if_neq(current);
+        // Rapier moves a body whose global transform is not the one it last wrote, so the
+        // drawn one must not reach it
+        global_transform.set_if_neq(current.into());
+    }
+}
+
+/// Keeps where the simulation left the interpolated entities, at the end of every step.
+pub fn record_simulated_transforms(mut query: Query<(&Transform, &mut Interpolated)>) {
+    for (transform, mut interpolated) in query.iter_mut() {
+        interpolated.previous = Some(interpolated.current.unwrap_or(*transform));
+        interpolated.current = Some(*transform);
+    }
+}
+
+fn interpolate_transforms(
+    fixed_time: Res<FixedTime>,
+    mut query: Query<(&mut Transform, &Interpolated)>,
+) {
+    // How far the next step is, as the time left over from the last one
+    let alpha = fixed_time.accumulated().as_secs_f32() / fixed_time.period.as_secs_f32();
+    let alpha = alpha.min(1.);
+    for (mut transform, interpolated) in query.iter_mut() {
+        let (Some(previous), Some(current)) = (interpolated.previous, interpolated.current) else {
+            continue;
+        };
+        transform.translation = previous.translation.lerp(current.translation, alpha);
+        transform.rotation = previous.rotation.slerp(current.rotation, alpha);
+    }
+}
@@ -1,16 +1,21 @@
-use bevy::{prelude::*, utils::HashMap, window::PrimaryWindow};
+use std::time::Duration;
+
+use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
 
 use crate::{
-    animation::{Animation, AnimationTimer, Animations},
-    effects::{Effect, EffectQueue},
-    events::WallReached,
+    actions::Action,
+    animation::Animator,
+    effects::{ActiveEffects, Effect},
+    events::{Landed, WallReached},
+    physics::{Arena, Interpolated},
+    sprites::{Sheet, Sprites},
     tiles::{Icy, Platform},
-    AppState, Wall,
+    AppState, GameplaySet, Wall,
 };
 
-const PLAYER_SPEED: f32 = 125.;
-const JUMP_VELOCITY: f32 = 150.;
+pub const PLAYER_SPEED: f32 = 125.;
+pub const JUMP_VELOCITY: f32 = 150.;
 const PLAYER_SIZE: f32 = 32.;
 const HALF_PLAYER_SIZE: f32 = PLAYER_SIZE / 2.;
 
@@ -24,101 +29,167 @@ pub enum Facing {
     Left,
 }
 
-#[derive(Component, DerefMut, Deref)]
-struct OnPlatform(pub Velocity);
-
 #[derive(Component, DerefMut, Deref)]
 pub struct LastWall(pub Wall);
 
+/// The movement actions held by the player during the current gameplay step,
+/// after the side effects had their say.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PlayerInput {
+    pub left: bool,
+    pub right: bool,
+    pub jump: bool,
+}
+
+/// How forgiving jumping is. Read when the player spawns, except for `jump_cut`.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct JumpSettings {
+    /// Seconds after walking off a platform during which the player can still jump.
+    pub coyote_time: f32,
+    /// Seconds a jump pressed before landing is kept for, to jump as soon as it lands.
+    pub jump_buffer: f32,
+    /// What is left of the upwards speed when the jump is released on the way up.
+    pub jump_cut: f32,
+}
+
+impl Default for JumpSettings {
+    fn default() -> Self {
+        JumpSettings {
+            coyote_time: 0.1,
+            jump_buffer: 0.1,
+            jump_cut: 0.5,
+        }
+    }
+}
+
+/// Whether the player stands on something, and what it takes to jump, across steps.
+#[derive(Component, Debug)]
+pub struct JumpState {
+    pub grounded: bool,
+    /// Runs since the player left the ground.
+    coyote: Timer,
+    /// Runs since the jump was pressed.
+    buffer: Timer,
+    /// Whether the jump was held on the previous step.
+    held: bool,
+    /// Whether the player goes up from a jump that can still be cut short.
+    rising: bool,
+}
+
+impl JumpState {
+    fn new(settings: &JumpSettings) -> JumpState {
+        JumpState {
+            grounded: false,
+            coyote: expired_timer(settings.coyote_time),
+            buffer: expired_timer(settings.jump_buffer),
+            held: false,
+            rising: false,
+        }
+    }
+
+    /// Takes the input of a step, and tells whether the player jumps.
+    fn update(&mut self, grounded: bool, jump: bool, delta: Duration) -> bool {
+        self.grounded = grounded;
+        if grounded {
+            self.coyote.reset();
+        } else {
+            self.coyote.tick(delta);
+        }
+        if jump && !self.held {
+            self.buffer.reset();
+        } else {
+            self.buffer.tick(delta);
+        }
+        self.held = jump;
+        if self.buffer.finished() || self.coyote.finished() {
+            return false;
+        }
+        // Both are used up, the next jump needs a new press and a new landing
+        expire(&mut self.buffer);
+        expire(&mut self.coyote);
+        self.rising = true;
+        true
+    }
+
+    /// The upwards speed after a step without a jump, cut short when the jump is let go
+    /// on the way up.
+    fn cut_jump(&mut self, jump: bool, speed: f32, jump_cut: f32) -> f32 {
+        if !self.rising || speed > 0. && jump {
+            return speed;
+        }
+        self.rising = false;
+        // Letting go of the jump on the way up makes it shorter
+        if speed > 0. {
+            speed * jump_cut
+        } else {
+            speed
+        }
+    }
+}
+
+fn expired_timer(secs: f32) -> Timer {
+    let mut timer = Timer::from_seconds(secs, TimerMode::Once);
+    expire(&mut timer);
+    timer
+}
+
+fn expire(timer: &mut Timer) {
+    let duration = timer.duration();
+    timer.tick(duration);
+}
+
+/// Systems writing the [`PlayerInput`] of the current step.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ReadInput;
+
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(spawn_player.in_schedule(OnEnter(AppState::InGame)))
+        app.init_resource::<PlayerInput>()
+            .init_resource::<JumpSettings>()
+            .add_system(spawn_player.in_schedule(OnEnter(AppState::InGame)))
+            .add_system(
+                read_actions
+                    // Without input devices the input is left to whoever drives the player
+                    .run_if(resource_exists::<Input<Action>>())
+                    .in_set(ReadInput)
+                    .in_set(GameplaySet::Input)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
             .add_systems(
                 (
                     handle_player_collisions,
                     player_input.after(handle_player_collisions),
                     remove_prismatic_joints_in_low_angles,
-                    change_player_animation,
                     send_wall_reached_event,
                     move_player,
                     confine_player_in_screen,
                 )
-                    .in_set(OnUpdate(AppState::InGame)),
+                    .in_set(GameplaySet::Simulation)
+                    .in_schedule(CoreSchedule::FixedUpdate),
             )
+            .add_system(change_player_animation.in_set(OnUpdate(AppState::InGame)))
             .add_system(despawn_player.in_schedule(OnExit(AppState::InGame)));
     }
 }
 
-fn spawn_player(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
-) {
-    let animations = HashMap::from_iter(vec![
-        (
-            "idle".to_owned(),
-            Animation {
-                handle: texture_atlases.add(TextureAtlas::from_grid(
-                    asset_server.load("sprites/idle.png"),
-                    Vec2::new(PLAYER_SIZE, PLAYER_SIZE),
-                    2,
-                    1,
-                    None,
-                    None,
-                )),
-                last: 2,
-                curr: 0,
-                fps: 6,
-            },
-        ),
-        (
-            "run".to_owned(),
-            Animation {
-                handle: texture_atlases.add(TextureAtlas::from_grid(
-                    asset_server.load("sprites/run.png"),
-                    Vec2::new(PLAYER_SIZE, PLAYER_SIZE),
-                    18,
-                    1,
-                    None,
-                    None,
-                )),
-                last: 2,
-                curr: 0,
-                fps: 12,
-            },
-        ),
-        (
-            "jump".to_owned(),
-            Animation {
-                handle: texture_atlases.add(TextureAtlas::from_grid(
-                    asset_server.load("sprites/jump.png"),
-                    Vec2::new(PLAYER_SIZE, PLAYER_SIZE),
-                    1,
-                    1,
-                    None,
-                    None,
-                )),
-                last: 1,
-                curr: 0,
-                fps: 1,
-            },
-        ),
-    ]);
-
-    let idle = animations["idle"].clone();
+fn spawn_player(mut commands: Commands, mut sprites: Sprites, jump_settings: Res<JumpSettings>) {
+    // The animator shows the atlas of its clip, so the sprite starts without one
+    let animator = Animator::new(sprites.clips(Sheet::Player), "idle")
+        .with_transitions(sprites.transitions(Sheet::Player));
 
     commands
         .spawn(Player)
         .insert(Facing::default())
+        .insert(JumpState::new(&jump_settings))
         .insert(SpriteSheetBundle {
-            texture_atlas: idle.handle.clone(),
             sprite: TextureAtlasSprite::new(0),
             transform: Transform::from_xyz(10., HALF_PLAYER_SIZE + 250., 499.),
             ..default()
         })
         .insert(RigidBody::Dynamic)
+        .insert(Interpolated::default())
         .insert(LockedAxes::ROTATION_LOCKED)
         .insert(GravityScale(4.))
         .insert(Velocity {
@@ -136,14 +207,7 @@ fn spawn_player(
         })
         .insert(Ccd::enabled())
         .insert(ActiveEvents::COLLISION_EVENTS)
-        .insert(AnimationTimer(Timer::from_seconds(
-            1. / idle.fps as f32,
-            TimerMode::Repeating,
-        )));
-    commands.insert_resource(Animations {
-        map: animations,
-        active: idle,
-    });
+        .insert(animator);
 }
 
 /* Read the character controller collisions stored in the character controller’s output. */
@@ -151,10 +215,12 @@ fn handle_player_collisions(
     wall_query: Query<&Wall>,
     plat_query: Query<(Option<&Icy>, &Velocity, &Transform), (With<Platform>, Without<Player>)>,
     mut commands: Commands,
+    mut landed_events: EventWriter<Landed>,
     mut character_controller_outputs: Query<
         (
             Entity,
             &Transform,
+            &Velocity,
             Option<&LastWall>,
             Option<&ImpulseJoint>,
             &KinematicCharacterControllerOutput,
@@ -162,7 +228,7 @@ fn handle_player_collisions(
         With<Player>,
     >,
 ) {
-    for (player, player_transform, last_wall, joint, output) in
+    for (player, player_transform, player_velocity, last_wall, joint, output) in
         character_controller_outputs.iter_mut()
     {
         for collision in &output.collisions {
@@ -201,6 +267,9 @@ fn handle_player_collisions(
                     )
                 };
                 commands.entity(player).insert(joint);
+                landed_events.send(Landed {
+                    fall_speed: f32::max(0., -player_velocity.linvel.y),
+                });
             }
         }
     }
@@ -227,62 +296,81 @@ fn remove_prismatic_joints_in_low_angles(
 fn confine_player_in_screen(
     mut commands: Commands,
     mut query: Query<(Entity, &mut Transform, &mut Velocity), With<Player>>,
-    window_query: Query<&Window, With<PrimaryWindow>>,
+    arena: Res<Arena>,
 ) {
-    let Ok(window) = window_query.get_single() else {
-        return;
-    };
     for (player, mut transform, mut velocity) in query.iter_mut() {
-        if transform.translation.x > window.width() || transform.translation.x < 0. {
+        if transform.translation.x > arena.width || transform.translation.x < 0. {
             velocity.linvel = Vec2::ZERO;
-            transform.translation.x = transform.translation.x.clamp(0., window.width());
+            transform.translation.x = transform.translation.x.clamp(0., arena.width);
             commands.entity(player).remove::<ImpulseJoint>();
         }
     }
 }
 
 fn move_player(
-    time: Res<Time>,
+    fixed_time: Res<FixedTime>,
     mut query: Query<(&Velocity, &mut KinematicCharacterController), With<Player>>,
 ) {
     for (velocity, mut controller) in query.iter_mut() {
-        controller.translation = Some(velocity.linvel * time.delta_seconds());
+        controller.translation = Some(velocity.linvel * fixed_time.period.as_secs_f32());
     }
 }
 
+fn read_actions(
+    actions: Res<Input<Action>>,
+    active_effects: Res<ActiveEffects>,
+    mut input: ResMut<PlayerInput>,
+) {
+    // Swapping the actions inverts every binding of every device
+    let (left, right) = if active_effects.contains(&Effect::InverseKeyboard) {
+        (Action::MoveRight, Action::MoveLeft)
+    } else {
+        (Action::MoveLeft, Action::MoveRight)
+    };
+    *input = PlayerInput {
+        left: actions.pressed(left),
+        right: actions.pressed(right),
+        jump: actions.pressed(Action::Jump),
+    };
+}
+
 fn player_input(
-    keyboard_input: Res<Input<KeyCode>>,
-    window_query: Query<&Window, With<PrimaryWindow>>,
-    effects_q: Res<EffectQueue>,
+    input: Res<PlayerInput>,
+    fixed_time: Res<FixedTime>,
+    jump_settings: Res<JumpSettings>,
     mut commands: Commands,
-    mut query: Query<(Entity, &Transform, &mut Velocity, &mut Facing), With<Player>>,
+    mut query: Query<
+        (
+            Entity,
+            &mut Velocity,
+            &mut Facing,
+            &mut JumpState,
+            Option<&KinematicCharacterControllerOutput>,
+            Option<&ImpulseJoint>,
+        ),
+        With<Player>,
+    >,
 ) {
-    let Ok(window) = window_query.get_single() else {
-        return;
-    };
-    for (player, transform, mut velocity, mut facing) in query.iter_mut() {
-        // TODO: This is a hack to prevent the player from falling off the screen.
-        // It should be replaced with a proper solution.
-        if transform.translation.y >= window.height() * 0.75 {
-            continue;
-        }
-        let (left, right) = match effects_q.last() {
-            Some(Effect::InverseKeyboard) => (KeyCode::D, KeyCode::A),
-            _ => (KeyCode::A, KeyCode::D),
-        };
-        if keyboard_input.pressed(left) {
+    for (player, mut velocity, mut facing, mut jump_state, output, joint) in query.iter_mut() {
+        // Standing on a platform joins the player to it, which the controller may not see
+        let grounded = joint.is_some() || output.is_some_and(|output| output.grounded);
+        let jumps = jump_state.update(grounded, input.jump, fixed_time.period);
+        if input.left {
             velocity.linvel.x = -PLAYER_SPEED;
             *facing = Facing::Left;
             commands.entity(player).remove::<ImpulseJoint>();
-        } else if keyboard_input.pressed(right) {
+        } else if input.right {
             velocity.linvel.x = PLAYER_SPEED;
             *facing = Facing::Right;
             commands.entity(player).remove::<ImpulseJoint>();
         }
-        if keyboard_input.pressed(KeyCode::Space) && velocity.linvel.y.abs() <= 0.001 {
-            velocity.linvel.y += JUMP_VELOCITY;
+        if jumps {
+            velocity.linvel.y = JUMP_VELOCITY;
             velocity.linvel.x = 0.;
             commands.entity(player).remove::<ImpulseJoint>();
+        } else if jump_state.rising {
+            velocity.linvel.y =
+                jump_state.cut_jump(input.jump, velocity.linvel.y, jump_settings.jump_cut);
         }
     }
 }
@@ -300,34 +388,14 @@ fn send_wall_reached_event(
 }
 
 fn change_player_animation(
-    keyboard_input: Res<Input<KeyCode>>,
-    mut commands: Commands,
-    mut query: Query<(Entity, &Velocity), With<Player>>,
-    mut animations: ResMut<Animations>,
+    input: Res<PlayerInput>,
+    mut query: Query<(&Velocity, &mut Animator), With<Player>>,
 ) {
-    for (player, velocity) in query.iter_mut() {
-        let old_animation_handle = animations.active.handle.clone();
-        animations.active = if velocity.linvel.y.abs() >= 0.025 {
-            &animations.map["jump"]
-        } else if velocity.linvel.x.abs() <= 0.001 {
-            &animations.map["idle"]
-        } else if keyboard_input.pressed(KeyCode::A) || keyboard_input.pressed(KeyCode::D) {
-            &animations.map["run"]
-        } else {
-            &animations.map["idle"]
-        }
-        .clone();
-        if animations.active.handle == old_animation_handle {
-            return;
-        }
-        commands.entity(player).insert((
-            animations.active.handle.clone(),
-            TextureAtlasSprite::new(animations.active.curr),
-            AnimationTimer(Timer::from_seconds(
-                1. / animations.active.fps as f32,
-                TimerMode::Repeating,
-            )),
-        ));
+    // The clip is picked by the transitions of the player sheet
+    for (velocity, mut animator) in query.iter_mut() {
+        animator.set("speed_x", velocity.linvel.x.abs());
+        animator.set("speed_y", velocity.linvel.y.abs());
+        animator.set("steering", f32::from(u8::from(input.left || input.right)));
     }
 }
 
@@ -336,3 +404,81 @@ fn despawn_player(mut commands: Commands, query: Query<Entity, With<Player>>) {
         commands.entity(entity).despawn_recursive();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::physics::FIXED_TIMESTEP;
+
+    fn step() -> Duration {
+        Duration::from_secs_f32(FIXED_TIMESTEP)
+    }
+
+    /// A player that stood on the ground and walked off it `steps` steps ago.
+    fn walked_off(steps: usize) -> JumpState {
+        let mut state = JumpState::new(&JumpSettings::default());
+        assert!(!state.update(true, false, step()));
+        for _ in 0..steps {
+            assert!(!state.update(false, false, step()));
+        }
+        state
+    }
+
+    #[test]
+    fn jumps_shortly_after_leaving_the_ground() {
+        // Along with the step jumping, 4 steps are less than the 0.1s of coyote time
+        let mut state = walked_off(3);
+        assert!(state.update(false, true, step()));
+        // The jump is used up until the next landing
+        assert!(!state.update(false, false, step()));
+        assert!(!state.update(false, true, step()));
+    }
+
+    #[test]
+    fn does_not_jump_once_coyote_time_is_over() {
+        let mut state = walked_off(7);
+        assert!(!state.update(false, true, step()));
+    }
+
+    #[test]
+    fn jumps_on_landing_when_pressed_shortly_before() {
+        let mut state = walked_off(10);
+        assert!(!state.update(false, true, step()));
+        assert!(!state.update(false, false, step()));
+        assert!(!state.update(false, false, step()));
+        assert!(state.update(true, false, step()));
+    }
+
+    #[test]
+    fn does_not_jump_on_landing_when_pressed_long_before() {
+        let mut state = walked_off(10);
+        assert!(!state.update(false, true, step()));
+        for _ in 0..7 {
+            assert!(!state.update(false, true, step()));
+        }
+        // Holding the jump is not pressing it again
+        assert!(!state.update(true, true, step()));
+    }
+
+    #[test]
+    fn releasing_the_jump_early_cuts_it_short() {
+        let jump_cut = JumpSettings::default().jump_cut;
+        let mut state = walked_off(0);
+        assert!(state.update(false, true, step()));
+        // Held on the way up, the jump goes on
+        assert_eq!(state.cut_jump(true, JUMP_VELOCITY, jump_cut), JUMP_VELOCITY);
+        assert_eq!(
+            state.cut_jump(false, JUMP_VELOCITY, jump_cut),
+            JUMP_VELOCITY * jump_cut
+        );
+        // Only once per jump
+        assert_eq!(state.cut_jump(false, 50., jump_cut), 50.);
+
+        // Past the top of the jump, there is nothing left to cut
+        let mut state = walked_off(0);
+        assert!(state.update(false, true, step()));
+        assert_eq!(state.cut_jump(true, -10., jump_cut), -10.);
+        assert_eq!(state.cut_jump(false, 10., jump_cut), 10.);
+    }
+}
@@ -0,0 +1,353 @@
+use crate::{
+    physics::FIXED_TIMESTEP,
+    player::{JUMP_VELOCITY, PLAYER_SPEED},
+};
+
+/// The player moves both as a Rapier body and through its character controller,
+/// so every step it covers twice its velocity.
+const PLAYER_MOTION_SCALE: f32 = 2.;
+/// Steps after which a jump is given up on, far longer than any jump between platforms.
+const MAX_JUMP_STEPS: usize = 600;
+
+/// The arc of a jump of the player, stepped like the simulation under a given gravity.
+#[derive(Debug, Clone, Copy)]
+pub struct JumpArc {
+    /// Downwards acceleration of the player, in pixels per second squared.
+    pub gravity: f32,
+}
+
+impl JumpArc {
+    /// `world_gravity` is the gravity of Rapier, scaled by the [`GravityScale`] of the player.
+    ///
+    /// [`GravityScale`]: bevy_rapier2d::prelude::GravityScale
+    pub fn new(world_gravity: f32, gravity_scale: f32) -> JumpArc {
+        JumpArc {
+            gravity: world_gravity.abs() * gravity_scale,
+        }
+    }
+
+    /// Height and horizontal speed of the player after each step of the jump.
+    fn steps(&self) -> impl Iterator<Item = (f32, f32)> + '_ {
+        let mut height = 0.;
+        let mut vertical_speed = JUMP_VELOCITY;
+        (0..MAX_JUMP_STEPS).map(move |_| {
+            vertical_speed -= self.gravity * FIXED_TIMESTEP;
+            height += PLAYER_MOTION_SCALE * vertical_speed * FIXED_TIMESTEP;
+            (height, vertical_speed)
+        })
+    }
+
+    /// Highest the player gets above where the jump started.
+    pub fn apex(&self) -> f32 {
+        self.steps()
+            .map(|(height, _)| height)
+            .take_while(|height| *height >= 0.)
+            .fold(0., f32::max)
+    }
+
+    /// How far the player gets horizontally before landing `drop` pixels below where the
+    /// jump started, towards a platform coming at it at `approach_speed`. `None` when the
+    /// landing is higher than the jump goes.
+    pub fn reach(&self, drop: f32, approach_speed: f32) -> Option<f32> {
+        if -drop > self.apex() {
+            return None;
+        }
+        let speed = PLAYER_MOTION_SCALE * PLAYER_SPEED + approach_speed;
+        self.steps()
+            .position(|(height, vertical_speed)| vertical_speed < 0. && height <= -drop)
+            // The jump step itself stops the player
+            .map(|step| step as f32 * FIXED_TIMESTEP * speed)
+    }
+}
+
+/// The part of a platform the player stands on, as seen when the next platform is emitted.
+#[derive(Debug, Clone, Copy)]
+pub struct PlatformSpan {
+    /// Horizontal center of the platform.
+    pub x: f32,
+    /// Height of the top of the platform.
+    pub top: f32,
+    pub half_width: f32,
+    /// Speed of the platform towards the wall the player comes from.
+    pub speed: f32,
+}
+
+/// Where the player jumps to the next platform from.
+#[derive(Debug, Clone, Copy)]
+pub enum Foothold {
+    /// The last platform of the crossing the player can stand on.
+    Platform(PlatformSpan),
+    /// The ledge the player is left on at the wall it comes from, at the start of a
+    /// crossing, with its top at this height.
+    Wall(f32),
+}
+
+/// Whether the player can jump from `from` to `to`, the platform emitted after it, while
+/// both are in play for `lifetime` seconds.
+pub fn is_reachable(jump: &JumpArc, from: &Foothold, to: &PlatformSpan, lifetime: f32) -> bool {
+    let from = match from {
+        Foothold::Platform(from) => from,
+        // The platform crosses the whole arena, so it comes to the player at the wall
+        Foothold::Wall(top) => return to.top - top <= jump.apex(),
+    };
+    let mut gap = (to.x - from.x).abs() - from.half_width - to.half_width;
+    // A faster platform ahead pulls away from the one behind it for as long as both are around
+    gap += (from.speed - to.speed).max(0.) * lifetime;
+    if gap <= 0. {
+        return to.top - from.top <= jump.apex();
+    }
+    jump.reach(from.top - to.top, to.speed)
+        .is_some_and(|reach| reach >= gap)
+}
+
+/// Whether the player can get from `platform` to the target wall, which it comes out from
+/// behind, before falling below `wall_bottom`.
+pub fn reaches_wall(jump: &JumpArc, platform: &PlatformSpan, wall_bottom: f32) -> bool {
+    // At best the player gets on as the platform comes out, at its far end, and runs back
+    // along it while it carries its near end away from the wall
+    let run_speed = PLAYER_MOTION_SCALE * PLAYER_SPEED;
+    let distance = platform.speed * 2. * platform.half_width / (run_speed + platform.speed);
+    // The wall is as high as the arena, so the player can land against it at any height
+    jump.reach(platform.top - wall_bottom, 0.)
+        .is_some_and(|reach| reach >= distance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effects::Effect;
+
+    /// Gravity of Rapier, in pixels per second squared.
+    const WORLD_GRAVITY: f32 = -98.1;
+    const NORMAL_GRAVITY: f32 = 4.;
+    const HIGH_GRAVITY: f32 = 8.;
+    const LOW_GRAVITY: f32 = 2.;
+    /// Speed of the platforms on the Normal difficulty.
+    const PLATFORM_SPEED: f32 = 125.;
+    /// Distance between two platforms emitted one after the other, at any platform speed.
+    const SPACING: f32 = 1.5 * PLATFORM_SPEED;
+    /// Half the width of the colliders of platforms of one, two and four parts.
+    const ONE_PART: f32 = 12.8;
+    const TWO_PARTS: f32 = 60.8;
+    const FOUR_PARTS: f32 = 124.8;
+
+    fn jump(gravity_scale: f32) -> JumpArc {
+        JumpArc::new(WORLD_GRAVITY, gravity_scale)
+    }
+
+    fn platform(x: f32, top: f32, half_width: f32, speed: f32) -> PlatformSpan {
+        PlatformSpan {
+            x,
+            top,
+            half_width,
+            speed,
+        }
+    }
+
+    /// Whether the platform emitted `spacings` platforms after `from` can be jumped to.
+    fn reachable_after(
+        jump: &JumpArc,
+        spacings: f32,
+        rise: f32,
+        half_widths: (f32, f32),
+        speed: f32,
+    ) -> bool {
+        let from = platform(0., 200., half_widths.0, speed);
+        let to = platform(SPACING * spacings, 200. + rise, half_widths.1, speed);
+        is_reachable(jump, &Foothold::Platform(from), &to, 5.)
+    }
+
+    #[test]
+    fn apex_follows_the_gravity() {
+        // Moving twice a step, the player goes twice as high as a body would
+        let expected = |gravity_scale: f32| {
+            JUMP_VELOCITY * JUMP_VELOCITY / (WORLD_GRAVITY.abs() * gravity_scale)
+        };
+        for gravity_scale in [NORMAL_GRAVITY, HIGH_GRAVITY, LOW_GRAVITY] {
+            let apex = jump(gravity_scale).apex();
+            assert!((apex - expected(gravity_scale)).abs() < 5., "{apex}");
+        }
+        assert!(jump(HIGH_GRAVITY).apex() < jump(NORMAL_GRAVITY).apex());
+        assert!(jump(LOW_GRAVITY).apex() > jump(NORMAL_GRAVITY).apex());
+    }
+
+    #[test]
+    fn reach_grows_with_the_drop_and_the_approach() {
+        let jump = jump(NORMAL_GRAVITY);
+        assert_eq!(jump.reach(-jump.apex() - 1., 0.), None);
+        let level = jump.reach(0., 0.).unwrap();
+        assert!(jump.reach(-20., 0.).unwrap() < level);
+        assert!(jump.reach(100., 0.).unwrap() > level);
+        assert!(jump.reach(0., PLATFORM_SPEED).unwrap() > level);
+    }
+
+    #[test]
+    fn normal_gravity() {
+        let jump = jump(NORMAL_GRAVITY);
+        let sizes = (ONE_PART, ONE_PART);
+        assert!(reachable_after(&jump, 1., 0., sizes, PLATFORM_SPEED));
+        assert!(reachable_after(&jump, 1., 40., sizes, PLATFORM_SPEED));
+        assert!(!reachable_after(&jump, 1., 60., sizes, PLATFORM_SPEED));
+    }
+
+    #[test]
+    fn high_gravity() {
+        let jump = jump(HIGH_GRAVITY);
+        // A small platform is too far, a wider one makes it
+        assert!(!reachable_after(
+            &jump,
+            1.,
+            0.,
+            (ONE_PART, ONE_PART),
+            PLATFORM_SPEED
+        ));
+        assert!(reachable_after(
+            &jump,
+            1.,
+            0.,
+            (TWO_PARTS, TWO_PARTS),
+            PLATFORM_SPEED
+        ));
+        // What a normal jump makes, a heavy one does not
+        assert!(!reachable_after(
+            &jump,
+            1.,
+            40.,
+            (TWO_PARTS, TWO_PARTS),
+            PLATFORM_SPEED
+        ));
+    }
+
+    #[test]
+    fn low_gravity() {
+        let jump = jump(LOW_GRAVITY);
+        assert!(reachable_after(
+            &jump,
+            1.,
+            100.,
+            (ONE_PART, ONE_PART),
+            PLATFORM_SPEED
+        ));
+        assert!(!reachable_after(
+            &jump,
+            1.,
+            120.,
+            (ONE_PART, ONE_PART),
+            PLATFORM_SPEED
+        ));
+    }
+
+    #[test]
+    fn fallthrough_platforms() {
+        // Falling through a platform, the player has to make it to the one after
+        let sizes = (ONE_PART, FOUR_PARTS);
+        assert!(reachable_after(
+            &jump(NORMAL_GRAVITY),
+            2.,
+            0.,
+            sizes,
+            PLATFORM_SPEED
+        ));
+        assert!(!reachable_after(
+            &jump(HIGH_GRAVITY),
+            2.,
+            0.,
+            sizes,
+            PLATFORM_SPEED
+        ));
+        assert!(reachable_after(
+            &jump(LOW_GRAVITY),
+            2.,
+            0.,
+            (ONE_PART, ONE_PART),
+            PLATFORM_SPEED
+        ));
+    }
+
+    #[test]
+    fn fast_platforms() {
+        // Faster platforms come at the player, which makes up for a heavy jump
+        let jump = jump(HIGH_GRAVITY);
+        let sizes = (ONE_PART, TWO_PARTS);
+        assert!(!reachable_after(&jump, 1.25, 0., sizes, PLATFORM_SPEED));
+        assert!(reachable_after(&jump, 1.25, 0., sizes, 2. * PLATFORM_SPEED));
+    }
+
+    #[test]
+    fn slow_platforms() {
+        let jump = jump(NORMAL_GRAVITY);
+        let sizes = (ONE_PART, ONE_PART);
+        assert!(reachable_after(&jump, 1.4, 0., sizes, PLATFORM_SPEED));
+        assert!(!reachable_after(
+            &jump,
+            1.4,
+            0.,
+            sizes,
+            0.5 * PLATFORM_SPEED
+        ));
+    }
+
+    #[test]
+    fn platforms_pulling_away() {
+        let jump = jump(NORMAL_GRAVITY);
+        let from = Foothold::Platform(platform(0., 200., TWO_PARTS, 2. * PLATFORM_SPEED));
+        let to = platform(SPACING, 200., TWO_PARTS, PLATFORM_SPEED);
+        assert!(is_reachable(&jump, &from, &to, 0.));
+        assert!(!is_reachable(&jump, &from, &to, 2.));
+    }
+
+    #[test]
+    fn every_effect_keeps_consecutive_platforms_reachable() {
+        for effect in [
+            Effect::Earthquake,
+            Effect::FastPlatforms,
+            Effect::SlowPlatforms,
+            Effect::InverseKeyboard,
+            Effect::FallthroughPlatforms,
+            Effect::HighGravity,
+            Effect::LowGravity,
+            Effect::Darkness,
+            Effect::IcyPlatforms,
+            Effect::SlowMo,
+            Effect::FastMo,
+            Effect::ViolentLandings,
+        ] {
+            let gravity_scale = match effect {
+                Effect::HighGravity => HIGH_GRAVITY,
+                Effect::LowGravity => LOW_GRAVITY,
+                _ => NORMAL_GRAVITY,
+            };
+            let speed = match effect {
+                Effect::FastPlatforms => 2. * PLATFORM_SPEED,
+                Effect::SlowPlatforms => 0.5 * PLATFORM_SPEED,
+                // Icy platforms move as fast, the player only slides on them
+                _ => PLATFORM_SPEED,
+            };
+            assert!(
+                reachable_after(&jump(gravity_scale), 1., 0., (TWO_PARTS, TWO_PARTS), speed),
+                "{effect}"
+            );
+        }
+    }
+
+    #[test]
+    fn from_the_wall() {
+        let jump = jump(NORMAL_GRAVITY);
+        let far = platform(1000., 200., ONE_PART, PLATFORM_SPEED);
+        assert!(is_reachable(&jump, &Foothold::Wall(160.), &far, 0.));
+        assert!(is_reachable(&jump, &Foothold::Wall(250.), &far, 0.));
+        assert!(!is_reachable(&jump, &Foothold::Wall(100.), &far, 0.));
+    }
+
+    #[test]
+    fn to_the_wall() {
+        let wide = platform(0., 160., FOUR_PARTS, PLATFORM_SPEED);
+        assert!(reaches_wall(&jump(NORMAL_GRAVITY), &wide, 0.));
+        assert!(reaches_wall(&jump(HIGH_GRAVITY), &wide, 0.));
+        // Fast enough, a wide platform carries the player away from the wall
+        let fast = platform(0., 160., FOUR_PARTS, 2000.);
+        assert!(!reaches_wall(&jump(HIGH_GRAVITY), &fast, 0.));
+        let narrow = platform(0., 160., ONE_PART, 2000.);
+        assert!(reaches_wall(&jump(HIGH_GRAVITY), &narrow, 0.));
+    }
+}
@@ -0,0 +1,454 @@
+use std::{fmt, fs, io, path::Path};
+
+use bevy::prelude::*;
+
+use crate::{
+    binary::{self, Reader, Truncated},
+    difficulty::{Difficulty, DifficultyLevel},
+    events::GameTimer,
+    physics::FIXED_TIMESTEP,
+    player::{PlayerInput, ReadInput},
+    rng::{NextRunSeed, RunSeed},
+    save::data_path,
+    settings::WallTimerMode,
+    AppState, GameMode, GameplaySet,
+};
+
+/// Where the recording of the last run is saved, inside the data directory.
+const LAST_RECORDING_PATH: &str = "replays/last.ssrp";
+
+const MAGIC: &[u8; 4] = b"SSRP";
+const VERSION: u8 = 3;
+/// Recordings from before the timer mode was part of the header.
+const VERSION_WITHOUT_TIMER_MODE: u8 = 1;
+/// Recordings from before the difficulty was part of the header.
+const VERSION_WITHOUT_DIFFICULTY: u8 = 2;
+/// Most steps a recording is read back with, four hours of play.
+const MAX_RECORDING_STEPS: usize = (4. * 60. * 60. / FIXED_TIMESTEP) as usize;
+
+const LEFT_BIT: u8 = 1 << 0;
+const RIGHT_BIT: u8 = 1 << 1;
+const JUMP_BIT: u8 = 1 << 2;
+
+/// The input of every gameplay step of a run, along with what is needed to replay it.
+#[derive(Debug, Clone)]
+pub struct Recording {
+    pub seed: RunSeed,
+    pub mode: GameMode,
+    pub timer_mode: WallTimerMode,
+    pub difficulty: DifficultyLevel,
+    pub inputs: Vec<PlayerInput>,
+}
+
+#[derive(Debug)]
+pub enum RecordingError {
+    Io(io::Error),
+    NotARecording,
+    UnsupportedVersion(u8),
+    InvalidGameMode(u8),
+    InvalidTimerMode(u8),
+    InvalidDifficulty(u8),
+    InvalidRunLength,
+    TooLong,
+    Truncated,
+}
+
+impl fmt::Display for RecordingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordingError::Io(err) => write!(f, "{err}"),
+            RecordingError::NotARecording => write!(f, "not a SideSwap recording"),
+            RecordingError::UnsupportedVersion(version) => {
+                write!(f, "unsupported recording version {version}")
+            }
+            RecordingError::InvalidGameMode(mode) => write!(f, "invalid game mode {mode}"),
+            RecordingError::InvalidTimerMode(mode) => write!(f, "invalid timer mode {mode}"),
+            RecordingError::InvalidDifficulty(difficulty) => {
+                write!(f, "invalid difficulty {difficulty}")
+            }
+            RecordingError::InvalidRunLength => write!(f, "invalid run length"),
+            RecordingError::TooLong => {
+                write!(f, "recording is longer than {MAX_RECORDING_STEPS} steps")
+            }
+            RecordingError::Truncated => write!(f, "recording is truncated"),
+        }
+    }
+}
+
+impl From<io::Error> for RecordingError {
+    fn from(err: io::Error) -> Self {
+        RecordingError::Io(err)
+    }
+}
+
+impl From<Truncated> for RecordingError {
+    fn from(_: Truncated) -> Self {
+        RecordingError::Truncated
+    }
+}
+
+impl Recording {
+    /// Encodes the recording as a header followed by run-length encoded inputs:
+    ///
+    /// `"SSRP" | version: u8 | mode: u8 | timer mode: u8 | difficulty: u8 | seed: u64 | runs: u32 | (input: u8, length: varint)*`
+    ///
+    /// with integers in little endian.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut runs: Vec<(u8, u32)> = vec![];
+        for input in self.inputs.iter() {
+            let bits = input_to_bits(input);
+            match runs.last_mut() {
+                Some((last, length)) if *last == bits => *length += 1,
+                _ => runs.push((bits, 1)),
+            }
+        }
+
+        let mut bytes = binary::header(MAGIC, VERSION);
+        bytes.push(match self.mode {
+            GameMode::Normal => 0,
+            GameMode::Hard => 1,
+        });
+        bytes.push(match self.timer_mode {
+            WallTimerMode::PerWall => 0,
+            WallTimerMode::Bank => 1,
+        });
+        bytes.push(match self.difficulty {
+            DifficultyLevel::Easy => 0,
+            DifficultyLevel::Normal => 1,
+            DifficultyLevel::Hard => 2,
+        });
+        bytes.extend_from_slice(&self.seed.0.to_le_bytes());
+        bytes.extend_from_slice(&(runs.len() as u32).to_le_bytes());
+        for (bits, mut length) in runs {
+            bytes.push(bits);
+            // LEB128, 7 bits at a time
+            loop {
+                let byte = (length & 0x7F) as u8;
+                length >>= 7;
+                if length == 0 {
+                    bytes.push(byte);
+                    break;
+                }
+                bytes.push(byte | 0x80);
+            }
+        }
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Recording, RecordingError> {
+        let mut reader = Reader::new(bytes);
+        if !reader.magic(MAGIC)? {
+            return Err(RecordingError::NotARecording);
+        }
+        let version = reader.u8()?;
+        if !(VERSION_WITHOUT_TIMER_MODE..=VERSION).contains(&version) {
+            return Err(RecordingError::UnsupportedVersion(version));
+        }
+        let mode = match reader.u8()? {
+            0 => GameMode::Normal,
+            1 => GameMode::Hard,
+            mode => return Err(RecordingError::InvalidGameMode(mode)),
+        };
+        let timer_mode = if version <= VERSION_WITHOUT_TIMER_MODE {
+            WallTimerMode::PerWall
+        } else {
+            match reader.u8()? {
+                0 => WallTimerMode::PerWall,
+                1 => WallTimerMode::Bank,
+                mode => return Err(RecordingError::InvalidTimerMode(mode)),
+            }
+        };
+        let difficulty = if version <= VERSION_WITHOUT_DIFFICULTY {
+            DifficultyLevel::Normal
+        } else {
+            match reader.u8()? {
+                0 => DifficultyLevel::Easy,
+                1 => DifficultyLevel::Normal,
+                2 => DifficultyLevel::Hard,
+                difficulty => return Err(RecordingError::InvalidDifficulty(difficulty)),
+            }
+        };
+        let seed = RunSeed(reader.u64()?);
+        let run_count = reader.u32()?;
+
+        let mut inputs = vec![];
+        for _ in 0..run_count {
+            let input = input_from_bits(reader.u8()?);
+            let mut length = 0u32;
+            let mut shift = 0;
+            loop {
+                let byte = reader.u8()?;
+                // Only the top 4 bits of a u32 are left for the fifth byte
+                if shift == 28 && byte > 0x0F {
+                    return Err(RecordingError::InvalidRunLength);
+                }
+                length |= ((byte & 0x7F) as u32) << shift;
+                if byte & 0x80 == 0 {
+                    break;
+                }
+                shift += 7;
+            }
+            if inputs.len() + length as usize > MAX_RECORDING_STEPS {
+                return Err(RecordingError::TooLong);
+            }
+            inputs.extend(std::iter::repeat(input).take(length as usize));
+        }
+
+        Ok(Recording {
+            seed,
+            mode,
+            timer_mode,
+            difficulty,
+            inputs,
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), RecordingError> {
+        binary::save(path.as_ref(), &self.encode())?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Recording, RecordingError> {
+        Recording::decode(&fs::read(path)?)
+    }
+}
+
+fn input_to_bits(input: &PlayerInput) -> u8 {
+    let mut bits = 0;
+    if input.left {
+        bits |= LEFT_BIT;
+    }
+    if input.right {
+        bits |= RIGHT_BIT;
+    }
+    if input.jump {
+        bits |= JUMP_BIT;
+    }
+    bits
+}
+
+fn input_from_bits(bits: u8) -> PlayerInput {
+    PlayerInput {
+        left: bits & LEFT_BIT != 0,
+        right: bits & RIGHT_BIT != 0,
+        jump: bits & JUMP_BIT != 0,
+    }
+}
+
+/// Inputs of the current run so far.
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct RecordedInputs(pub Vec<PlayerInput>);
+
+/// Present while a recording is played back in place of the keyboard.
+#[derive(Resource)]
+pub struct Playback {
+    inputs: Vec<PlayerInput>,
+    step: usize,
+    pub mode: GameMode,
+    pub timer_mode: WallTimerMode,
+    pub difficulty: DifficultyLevel,
+}
+
+/// Sent to play back the recording of the last run.
+pub struct WatchReplay;
+
+pub struct RecordingPlugin;
+
+impl Plugin for RecordingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RecordedInputs>()
+            .add_event::<WatchReplay>()
+            .add_system(start_recording.in_schedule(OnEnter(AppState::InGame)))
+            .add_systems(
+                (
+                    play_back_input.run_if(resource_exists::<Playback>()),
+                    record_input,
+                )
+                    .chain()
+                    .after(ReadInput)
+                    .in_set(GameplaySet::Input)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(save_recording.in_schedule(OnExit(AppState::InGame)))
+            .add_system(start_playback);
+    }
+}
+
+fn start_recording(mut recorded_inputs: ResMut<RecordedInputs>) {
+    recorded_inputs.clear();
+}
+
+fn play_back_input(mut playback: ResMut<Playback>, mut input: ResMut<PlayerInput>) {
+    *input = playback
+        .inputs
+        .get(playback.step)
+        .copied()
+        .unwrap_or_default();
+    playback.step += 1;
+}
+
+fn record_input(input: Res<PlayerInput>, mut recorded_inputs: ResMut<RecordedInputs>) {
+    recorded_inputs.push(*input);
+}
+
+pub fn save_recording(
+    mut commands: Commands,
+    playback: Option<Res<Playback>>,
+    seed: Res<RunSeed>,
+    game_mode: Res<GameMode>,
+    timer: Res<GameTimer>,
+    difficulty: Res<Difficulty>,
+    mut recorded_inputs: ResMut<RecordedInputs>,
+) {
+    // Watching a replay does not overwrite it
+    if playback.is_some() {
+        commands.remove_resource::<Playback>();
+        return;
+    }
+    let recording = Recording {
+        seed: *seed,
+        mode: *game_mode,
+        timer_mode: timer.mode,
+        difficulty: difficulty.level,
+        inputs: std::mem::take(&mut recorded_inputs.0),
+    };
+    let path = data_path(LAST_RECORDING_PATH);
+    if let Err(err) = recording.save(&path) {
+        warn!("Could not save the recording to {}: {err}", path.display());
+    }
+}
+
+fn start_playback(
+    mut commands: Commands,
+    mut events: EventReader<WatchReplay>,
+    mut next_seed: ResMut<NextRunSeed>,
+    mut state: ResMut<NextState<AppState>>,
+) {
+    if events.iter().last().is_none() {
+        return;
+    }
+    let path = data_path(LAST_RECORDING_PATH);
+    let recording = match Recording::load(&path) {
+        Ok(recording) => recording,
+        Err(err) => {
+            warn!(
+                "Could not load the recording from {}: {err}",
+                path.display()
+            );
+            return;
+        }
+    };
+    next_seed.0 = Some(recording.seed);
+    commands.insert_resource(Playback {
+        inputs: recording.inputs,
+        step: 0,
+        mode: recording.mode,
+        timer_mode: recording.timer_mode,
+        difficulty: recording.difficulty,
+    });
+    state.set(AppState::InGame);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(left: bool, right: bool, jump: bool) -> PlayerInput {
+        PlayerInput { left, right, jump }
+    }
+
+    fn recording(inputs: Vec<PlayerInput>) -> Recording {
+        Recording {
+            seed: RunSeed(0x0123_4567_89AB_CDEF),
+            mode: GameMode::Hard,
+            timer_mode: WallTimerMode::Bank,
+            difficulty: DifficultyLevel::Easy,
+            inputs,
+        }
+    }
+
+    /// A header of `version` for a Hard run on `seed` 1, before its runs.
+    fn header(version: u8) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(version);
+        bytes.push(1);
+        if version > VERSION_WITHOUT_TIMER_MODE {
+            bytes.push(1);
+        }
+        if version > VERSION_WITHOUT_DIFFICULTY {
+            bytes.push(2);
+        }
+        bytes.extend_from_slice(&1u64.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn round_trips_runs_of_any_length() {
+        let mut inputs = vec![input(false, false, false); 3];
+        // Runs needing one, two and three bytes of length
+        inputs.extend(std::iter::repeat(input(true, false, true)).take(127));
+        inputs.extend(std::iter::repeat(input(false, true, false)).take(128));
+        inputs.extend(std::iter::repeat(input(true, true, true)).take(20_000));
+        inputs.push(input(false, false, true));
+
+        let decoded = Recording::decode(&recording(inputs.clone()).encode()).unwrap();
+        assert_eq!(decoded.seed, RunSeed(0x0123_4567_89AB_CDEF));
+        assert_eq!(decoded.mode, GameMode::Hard);
+        assert_eq!(decoded.timer_mode, WallTimerMode::Bank);
+        assert_eq!(decoded.difficulty, DifficultyLevel::Easy);
+        assert_eq!(decoded.inputs, inputs);
+    }
+
+    #[test]
+    fn encodes_lengths_as_leb128() {
+        let bytes = recording(vec![input(false, true, false); 300]).encode();
+        // One run, of 300 = 0b10_0101100 steps
+        assert_eq!(
+            bytes[bytes.len() - 7..],
+            [1, 0, 0, 0, RIGHT_BIT, 0xAC, 0x02]
+        );
+    }
+
+    #[test]
+    fn reads_older_headers() {
+        for (version, timer_mode) in [
+            (VERSION_WITHOUT_TIMER_MODE, WallTimerMode::PerWall),
+            (VERSION_WITHOUT_DIFFICULTY, WallTimerMode::Bank),
+        ] {
+            let mut bytes = header(version);
+            bytes.extend_from_slice(&1u32.to_le_bytes());
+            bytes.extend_from_slice(&[JUMP_BIT, 2]);
+
+            let decoded = Recording::decode(&bytes).unwrap();
+            assert_eq!(decoded.seed, RunSeed(1));
+            assert_eq!(decoded.mode, GameMode::Hard);
+            assert_eq!(decoded.timer_mode, timer_mode);
+            assert_eq!(decoded.difficulty, DifficultyLevel::Normal);
+            assert_eq!(decoded.inputs, vec![input(false, false, true); 2]);
+        }
+    }
+
+    #[test]
+    fn rejects_overlong_run_lengths() {
+        let mut bytes = header(VERSION);
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&[0, 0xFF, 0xFF, 0xFF, 0xFF, 0x1F]);
+        assert!(matches!(
+            Recording::decode(&bytes),
+            Err(RecordingError::InvalidRunLength)
+        ));
+    }
+
+    #[test]
+    fn rejects_too_many_steps() {
+        // A handful of bytes asking for billions of steps
+        let mut bytes = header(VERSION);
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        bytes.extend_from_slice(&[0, 0xFF, 0xFF, 0xFF, 0xFF, 0x0F]);
+        bytes.extend_from_slice(&[1, 0xFF, 0xFF, 0xFF, 0xFF, 0x0F]);
+        assert!(matches!(
+            Recording::decode(&bytes),
+            Err(RecordingError::TooLong)
+        ));
+    }
+}
@@ -0,0 +1,64 @@
+use bevy::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::AppState;
+
+/// Seed of the current run. Runs started from the same seed draw the same effects
+/// and platforms, in the same order.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct RunSeed(pub u64);
+
+impl std::fmt::Display for RunSeed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:016X}", self.0)
+    }
+}
+
+impl std::str::FromStr for RunSeed {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        u64::from_str_radix(s.trim(), 16).map(RunSeed)
+    }
+}
+
+/// Seed to start the next run from, a random one is picked when empty.
+#[derive(Resource, Debug, Default, Deref, DerefMut)]
+pub struct NextRunSeed(pub Option<RunSeed>);
+
+/// Random stream used to draw side effects.
+#[derive(Resource, Deref, DerefMut)]
+pub struct EffectRng(pub StdRng);
+
+/// Random stream used to generate platforms.
+#[derive(Resource, Deref, DerefMut)]
+pub struct PlatformRng(pub StdRng);
+
+// Each stream gets its own seed so drawing from one never shifts the other
+const EFFECT_STREAM: u64 = 0x5EED_0000_EFFE_C700;
+const PLATFORM_STREAM: u64 = 0x5EED_0000_9A7F_0A00;
+
+/// Systems seeding the random streams when a run starts.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SeedRun;
+
+pub struct RngPlugin;
+
+impl Plugin for RngPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NextRunSeed>().add_system(
+            seed_run
+                .in_set(SeedRun)
+                .in_schedule(OnEnter(AppState::InGame)),
+        );
+    }
+}
+
+fn seed_run(mut commands: Commands, mut next_seed: ResMut<NextRunSeed>) {
+    let seed = next_seed.take().unwrap_or_else(|| RunSeed(rand::random()));
+    commands.insert_resource(seed);
+    commands.insert_resource(EffectRng(StdRng::seed_from_u64(seed.0 ^ EFFECT_STREAM)));
+    commands.insert_resource(PlatformRng(StdRng::seed_from_u64(seed.0 ^ PLATFORM_STREAM)));
+}
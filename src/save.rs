@@ -0,0 +1,304 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    difficulty::DifficultyLevel,
+    events::{Lost, Reason, WallReached},
+    leaderboard::Leaderboard,
+    settings::{KeyBindings, Volume, WallTimerMode},
+    shake::ScreenShake,
+    AppState, GameMode, HighScore, Score,
+};
+
+/// Version of the save file, bumped whenever its layout changes.
+const SAVE_VERSION: u32 = 1;
+const SAVE_FILE_NAME: &str = "save.ron";
+
+/// Statistics over every run ever played.
+#[derive(Resource, Debug, Default, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Stats {
+    pub runs: u32,
+    pub walls_reached: u32,
+    pub deaths: u32,
+    pub out_of_time: u32,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Settings {
+    mode: GameMode,
+    volume: Volume,
+    key_bindings: KeyBindings,
+    screen_shake: ScreenShake,
+    wall_timer_mode: WallTimerMode,
+    difficulty: DifficultyLevel,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct SaveFile {
+    version: u32,
+    high_score: usize,
+    leaderboard: Leaderboard,
+    settings: Settings,
+    stats: Stats,
+}
+
+/// Only the version, to find out how to read the rest of the file.
+#[derive(Deserialize)]
+struct SaveVersion {
+    version: u32,
+}
+
+/// The directory the game keeps its files in, inside the data directory of the platform.
+pub fn data_dir() -> PathBuf {
+    let env_dir = |name: &str| std::env::var_os(name).map(PathBuf::from);
+    let home = || env_dir("HOME");
+    let base = if cfg!(target_os = "windows") {
+        env_dir("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        home().map(|home| home.join("Library/Application Support"))
+    } else {
+        env_dir("XDG_DATA_HOME").or_else(|| home().map(|home| home.join(".local/share")))
+    };
+    base.unwrap_or_default().join("SideSwap")
+}
+
+/// A path inside [`data_dir`].
+pub fn data_path(path: impl AsRef<Path>) -> PathBuf {
+    data_dir().join(path)
+}
+
+/// Loads the high score, settings and stats on startup, and saves them whenever they change.
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        let save = load_save(&data_path(SAVE_FILE_NAME));
+        app.insert_resource(HighScore(save.high_score))
+            .insert_resource(save.leaderboard)
+            .insert_resource(save.settings.mode)
+            .insert_resource(save.settings.volume)
+            .insert_resource(save.settings.key_bindings)
+            .insert_resource(save.settings.screen_shake)
+            .insert_resource(save.settings.wall_timer_mode)
+            .insert_resource(save.settings.difficulty)
+            .insert_resource(save.stats)
+            .add_system(count_run.in_schedule(OnEnter(AppState::InGame)))
+            .add_system(update_highscore.in_set(OnUpdate(AppState::InGame)))
+            .add_system(update_stats)
+            .add_system(write_save.after(update_stats));
+    }
+}
+
+fn update_highscore(score: Res<Score>, mut highscore: ResMut<HighScore>) {
+    if score.0 > highscore.0 {
+        highscore.0 = score.0;
+    }
+}
+
+fn load_save(path: &Path) -> SaveFile {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return SaveFile::default(),
+        Err(err) => {
+            warn!("Could not read the save file {}: {err}", path.display());
+            return SaveFile::default();
+        }
+    };
+    let save = ron::from_str::<SaveVersion>(&text)
+        .map_err(|err| err.to_string())
+        .and_then(|SaveVersion { version }| match version {
+            SAVE_VERSION => ron::from_str::<SaveFile>(&text).map_err(|err| err.to_string()),
+            // Older versions would be migrated here
+            version => Err(format!("unsupported save version {version}")),
+        });
+    match save {
+        Ok(save) => save,
+        Err(err) => {
+            // Keep the unreadable file around instead of overwriting it
+            let backup = path.with_extension("ron.bak");
+            warn!(
+                "Could not load the save file {}, backing it up to {}: {err}",
+                path.display(),
+                backup.display()
+            );
+            if let Err(err) = fs::rename(path, &backup) {
+                warn!("Could not back up the save file: {err}");
+            }
+            SaveFile::default()
+        }
+    }
+}
+
+fn count_run(mut stats: ResMut<Stats>) {
+    stats.runs += 1;
+}
+
+fn update_stats(
+    mut stats: ResMut<Stats>,
+    mut wall_reached: EventReader<WallReached>,
+    mut lost: EventReader<Lost>,
+) {
+    for _ in wall_reached.iter() {
+        stats.walls_reached += 1;
+    }
+    // Only the first loss ends the run
+    if let Some(Lost(reason)) = lost.iter().next() {
+        match reason {
+            Reason::Died => stats.deaths += 1,
+            Reason::OutOfTime => stats.out_of_time += 1,
+        }
+    }
+}
+
+fn write_save(
+    high_score: Res<HighScore>,
+    leaderboard: Res<Leaderboard>,
+    game_mode: Res<GameMode>,
+    volume: Res<Volume>,
+    key_bindings: Res<KeyBindings>,
+    screen_shake: Res<ScreenShake>,
+    wall_timer_mode: Res<WallTimerMode>,
+    difficulty_level: Res<DifficultyLevel>,
+    stats: Res<Stats>,
+) {
+    if !(high_score.is_changed()
+        || leaderboard.is_changed()
+        || game_mode.is_changed()
+        || volume.is_changed()
+        || key_bindings.is_changed()
+        || screen_shake.is_changed()
+        || wall_timer_mode.is_changed()
+        || difficulty_level.is_changed()
+        || stats.is_changed())
+    {
+        return;
+    }
+    let save = SaveFile {
+        version: SAVE_VERSION,
+        high_score: high_score.0,
+        leaderboard: leaderboard.clone(),
+        settings: Settings {
+            mode: *game_mode,
+            volume: *volume,
+            key_bindings: *key_bindings,
+            screen_shake: *screen_shake,
+            wall_timer_mode: *wall_timer_mode,
+            difficulty: *difficulty_level,
+        },
+        stats: *stats,
+    };
+    if let Err(err) = write_save_file(&data_path(SAVE_FILE_NAME), &save) {
+        warn!("Could not write the save file: {err}");
+    }
+}
+
+fn write_save_file(path: &Path, save: &SaveFile) -> io::Result<()> {
+    let text = ron::ser::to_string_pretty(save, ron::ser::PrettyConfig::default())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // Write next to the save file first, so a crash never leaves a half written save
+    let temp = path.with_extension("ron.tmp");
+    fs::write(&temp, text)?;
+    fs::rename(temp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A save file path in a directory of its own, left empty.
+    fn temp_save_path(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("side-swap-{}-{test}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(SAVE_FILE_NAME)
+    }
+
+    fn to_ron(save: &SaveFile) -> String {
+        ron::to_string(save).unwrap()
+    }
+
+    #[test]
+    fn missing_file_loads_the_defaults() {
+        let path = temp_save_path("missing");
+        assert_eq!(to_ron(&load_save(&path)), to_ron(&SaveFile::default()));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn unreadable_file_is_backed_up() {
+        let path = temp_save_path("garbage");
+        fs::write(&path, "not a save file").unwrap();
+        assert_eq!(to_ron(&load_save(&path)), to_ron(&SaveFile::default()));
+        assert!(!path.exists());
+        let backup = path.with_extension("ron.bak");
+        assert_eq!(fs::read_to_string(backup).unwrap(), "not a save file");
+    }
+
+    #[test]
+    fn unknown_version_is_rejected() {
+        let path = temp_save_path("version");
+        fs::write(&path, "(version: 99, high_score: 5)").unwrap();
+        assert_eq!(load_save(&path).high_score, 0);
+        assert!(path.with_extension("ron.bak").exists());
+    }
+
+    #[test]
+    fn missing_fields_load_their_defaults() {
+        let path = temp_save_path("old");
+        fs::write(
+            &path,
+            "(version: 1, high_score: 42, settings: (volume: 0.5), stats: (runs: 3))",
+        )
+        .unwrap();
+        let save = load_save(&path);
+        assert_eq!(save.high_score, 42);
+        assert_eq!(save.settings.volume, Volume(0.5));
+        assert_eq!(save.settings.key_bindings, KeyBindings::default());
+        assert_eq!(save.settings.wall_timer_mode, WallTimerMode::default());
+        assert_eq!(save.stats.runs, 3);
+        assert_eq!(save.stats.deaths, 0);
+        assert!(save.leaderboard.is_empty());
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn round_trips() {
+        let path = temp_save_path("round-trip");
+        let save = SaveFile {
+            version: SAVE_VERSION,
+            high_score: 17,
+            leaderboard: Leaderboard::default(),
+            settings: Settings {
+                mode: GameMode::Hard,
+                volume: Volume(0.25),
+                key_bindings: KeyBindings {
+                    jump: KeyCode::W,
+                    ..default()
+                },
+                screen_shake: ScreenShake::default(),
+                wall_timer_mode: WallTimerMode::Bank,
+                difficulty: DifficultyLevel::Hard,
+            },
+            stats: Stats {
+                runs: 4,
+                walls_reached: 30,
+                deaths: 3,
+                out_of_time: 1,
+            },
+        };
+        write_save_file(&path, &save).unwrap();
+        assert!(!path.with_extension("ron.tmp").exists());
+        assert_eq!(to_ron(&load_save(&path)), to_ron(&save));
+    }
+}
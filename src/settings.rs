@@ -0,0 +1,112 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::actions::{Action, ACTIONS};
+
+/// Volume of the sound effects, from 0 to 1.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Volume(pub f32);
+
+impl Default for Volume {
+    fn default() -> Self {
+        Volume(1.)
+    }
+}
+
+impl Volume {
+    /// The next volume when cycling through the menu, from loud to muted and back.
+    pub fn next(&self) -> Volume {
+        let next = (self.0 * 4.).round() - 1.;
+        if next < 0. {
+            Volume(1.)
+        } else {
+            Volume(next / 4.)
+        }
+    }
+}
+
+/// How the time to reach the walls is counted.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WallTimerMode {
+    /// The timer starts over at every wall.
+    #[default]
+    PerWall,
+    /// One countdown for the whole run, every wall adds a bonus to what is left.
+    Bank,
+}
+
+impl WallTimerMode {
+    pub fn next(&self) -> WallTimerMode {
+        match self {
+            WallTimerMode::PerWall => WallTimerMode::Bank,
+            WallTimerMode::Bank => WallTimerMode::PerWall,
+        }
+    }
+}
+
+impl std::fmt::Display for WallTimerMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            WallTimerMode::PerWall => "Per Wall",
+            WallTimerMode::Bank => "Bank",
+        })
+    }
+}
+
+/// The key bound to each action, every action has its own key.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    #[serde(alias = "left")]
+    pub move_left: KeyCode,
+    #[serde(alias = "right")]
+    pub move_right: KeyCode,
+    pub jump: KeyCode,
+    pub pause: KeyCode,
+    pub back: KeyCode,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            move_left: KeyCode::A,
+            move_right: KeyCode::D,
+            jump: KeyCode::Space,
+            pause: KeyCode::P,
+            back: KeyCode::Escape,
+        }
+    }
+}
+
+impl KeyBindings {
+    pub fn key(&self, action: Action) -> KeyCode {
+        match action {
+            Action::MoveLeft => self.move_left,
+            Action::MoveRight => self.move_right,
+            Action::Jump => self.jump,
+            Action::Pause => self.pause,
+            Action::Back => self.back,
+        }
+    }
+
+    fn key_mut(&mut self, action: Action) -> &mut KeyCode {
+        match action {
+            Action::MoveLeft => &mut self.move_left,
+            Action::MoveRight => &mut self.move_right,
+            Action::Jump => &mut self.jump,
+            Action::Pause => &mut self.pause,
+            Action::Back => &mut self.back,
+        }
+    }
+
+    /// Binds the key to the action. An action already bound to the key
+    /// takes over the previous key of this one, so no key is bound twice.
+    pub fn bind(&mut self, action: Action, key: KeyCode) {
+        let previous = self.key(action);
+        if let Some(other) = ACTIONS.into_iter().find(|other| self.key(*other) == key) {
+            *self.key_mut(other) = previous;
+        }
+        *self.key_mut(action) = key;
+    }
+}
@@ -0,0 +1,145 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::events::{Lost, WallReached};
+
+/// Trauma-based camera shake. Any system can add trauma, which decays over time,
+/// and the camera is offset and rotated by the square of it using smooth noise.
+#[derive(Resource)]
+pub struct CameraShake {
+    trauma: f32,
+    /// Trauma lost per second.
+    pub decay: f32,
+    /// Camera offset in pixels at full trauma.
+    pub max_offset: f32,
+    /// Camera rotation in radians at full trauma.
+    pub max_roll: f32,
+    /// How fast the noise driving the shake changes.
+    pub frequency: f32,
+    elapsed: f32,
+}
+
+impl Default for CameraShake {
+    fn default() -> Self {
+        CameraShake {
+            trauma: 0.,
+            decay: 1.5,
+            max_offset: 16.,
+            max_roll: 0.05,
+            frequency: 15.,
+            elapsed: 0.,
+        }
+    }
+}
+
+impl CameraShake {
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0., 1.);
+    }
+
+    /// Raises the trauma to at least `amount`, used for continuous shaking.
+    pub fn sustain_trauma(&mut self, amount: f32) {
+        self.trauma = self.trauma.max(amount.clamp(0., 1.));
+    }
+}
+
+/// Accessibility setting scaling every camera shake.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScreenShake {
+    #[default]
+    Full,
+    Reduced,
+    Off,
+}
+
+impl ScreenShake {
+    pub fn scale(&self) -> f32 {
+        match self {
+            ScreenShake::Full => 1.,
+            ScreenShake::Reduced => 0.35,
+            ScreenShake::Off => 0.,
+        }
+    }
+
+    pub fn next(&self) -> ScreenShake {
+        match self {
+            ScreenShake::Full => ScreenShake::Reduced,
+            ScreenShake::Reduced => ScreenShake::Off,
+            ScreenShake::Off => ScreenShake::Full,
+        }
+    }
+}
+
+/// The resting position of a shaken camera.
+#[derive(Component, Deref, DerefMut)]
+pub struct ShakeOrigin(pub Vec3);
+
+const WALL_REACHED_TRAUMA: f32 = 0.3;
+const LOST_TRAUMA: f32 = 0.8;
+
+pub struct ShakePlugin;
+
+impl Plugin for ShakePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraShake>()
+            .init_resource::<ScreenShake>()
+            .add_system(add_event_trauma.before(shake_camera))
+            .add_system(shake_camera);
+    }
+}
+
+fn add_event_trauma(
+    mut shake: ResMut<CameraShake>,
+    mut wall_reached: EventReader<WallReached>,
+    mut lost: EventReader<Lost>,
+) {
+    for _ in wall_reached.iter() {
+        shake.add_trauma(WALL_REACHED_TRAUMA);
+    }
+    for _ in lost.iter() {
+        shake.add_trauma(LOST_TRAUMA);
+    }
+}
+
+fn shake_camera(
+    time: Res<Time>,
+    settings: Res<ScreenShake>,
+    mut shake: ResMut<CameraShake>,
+    mut camera_query: Query<(&ShakeOrigin, &mut Transform), With<Camera>>,
+) {
+    // Use the real time so the shake is not affected by slow motion or pausing
+    let delta = time.raw_delta_seconds();
+    shake.elapsed += delta;
+    let amount = shake.trauma.powi(2) * settings.scale();
+    let t = shake.elapsed * shake.frequency;
+    for (origin, mut transform) in camera_query.iter_mut() {
+        transform.translation = origin.0
+            + Vec3::new(
+                shake.max_offset * amount * noise(t, 0),
+                shake.max_offset * amount * noise(t, 1),
+                0.,
+            );
+        transform.rotation = Quat::from_rotation_z(shake.max_roll * amount * noise(t, 2));
+    }
+    shake.trauma = (shake.trauma - shake.decay * delta).max(0.);
+}
+
+/// Smooth 1D value noise in `[-1, 1]`, with `seed` selecting an independent curve.
+fn noise(t: f32, seed: u32) -> f32 {
+    let i = t.floor();
+    let f = t - i;
+    let smooth = f * f * (3. - 2. * f);
+    let a = hash(i as i32, seed);
+    let b = hash(i as i32 + 1, seed);
+    a + (b - a) * smooth
+}
+
+fn hash(i: i32, seed: u32) -> f32 {
+    let mut x = (i as u32).wrapping_mul(0x9E37_79B9) ^ seed.wrapping_mul(0x85EB_CA6B);
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7FEB_352D);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846C_A68B);
+    x ^= x >> 16;
+    (x as f32 / u32::MAX as f32) * 2. - 1.
+}
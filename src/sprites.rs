@@ -0,0 +1,513 @@
+use std::fmt;
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    ecs::system::SystemParam,
+    prelude::*,
+    reflect::TypeUuid,
+    utils::{BoxedFuture, HashMap},
+};
+use serde::Deserialize;
+
+use crate::animation::{Clip, Clips, PlayMode, Transition};
+
+/// The sprite sheets of the gameplay entities, each one described by its own asset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Sheet {
+    Player,
+    Wall,
+    Platform,
+}
+
+const SHEETS: [Sheet; 3] = [Sheet::Player, Sheet::Wall, Sheet::Platform];
+
+impl Sheet {
+    /// Where the sheet is, inside the assets.
+    fn path(&self) -> &'static str {
+        match self {
+            Sheet::Player => "sprites/player.sheet.ron",
+            Sheet::Wall => "textures/wall.sheet.ron",
+            Sheet::Platform => "sprites/platform.sheet.ron",
+        }
+    }
+
+    /// The sheet as shipped, for when the assets are not loaded.
+    fn builtin(&self) -> &'static str {
+        match self {
+            Sheet::Player => include_str!("../assets/sprites/player.sheet.ron"),
+            Sheet::Wall => include_str!("../assets/textures/wall.sheet.ron"),
+            Sheet::Platform => include_str!("../assets/sprites/platform.sheet.ron"),
+        }
+    }
+
+    /// The atlases and clips the game asks the sheet for, by name.
+    fn required(&self) -> (&'static [&'static str], &'static [&'static str]) {
+        match self {
+            Sheet::Player => (&[], &["idle", "run", "jump"]),
+            Sheet::Wall => (&["glow"], &["glow"]),
+            Sheet::Platform => (&["platform"], &[]),
+        }
+    }
+}
+
+impl fmt::Display for Sheet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Sheet::Player => "player",
+            Sheet::Wall => "wall",
+            Sheet::Platform => "platform",
+        })
+    }
+}
+
+/// An image cut in a grid of `columns` by `rows` tiles, numbered row by row.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AtlasDefinition {
+    pub image: String,
+    pub tile_size: Vec2,
+    pub columns: usize,
+    pub rows: usize,
+}
+
+impl AtlasDefinition {
+    /// Whether the grid fits in an image of `image_size`.
+    pub fn fits(&self, image_size: Vec2) -> bool {
+        let grid_size = self.tile_size * Vec2::new(self.columns as f32, self.rows as f32);
+        grid_size.cmple(image_size).all()
+    }
+}
+
+/// A [`Clip`] of a sheet, with its atlas given by name.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ClipDefinition {
+    pub atlas: String,
+    #[serde(default)]
+    pub first: usize,
+    pub len: usize,
+    pub fps: f32,
+    #[serde(default)]
+    pub mode: PlayMode,
+}
+
+/// The atlases of an entity, the clips it plays from them and the transitions picking its
+/// clip, loaded from a `.sheet.ron` asset.
+#[derive(Debug, Clone, Deserialize, TypeUuid)]
+#[uuid = "673b1090-bace-46d2-89cd-c77f524f82ef"]
+pub struct SpriteSheet {
+    pub atlases: HashMap<String, AtlasDefinition>,
+    #[serde(default)]
+    pub clips: HashMap<String, ClipDefinition>,
+    #[serde(default)]
+    pub transitions: Vec<Transition>,
+}
+
+#[derive(Debug)]
+pub enum SpriteSheetError {
+    Ron(ron::error::SpannedError),
+    EmptyAtlas(String),
+    EmptyClip(String),
+    InvalidFps(String),
+    UnknownAtlas { clip: String, atlas: String },
+    OutOfAtlas(String),
+    UnknownNextClip { clip: String, next: String },
+    UnknownTransitionClip(String),
+    MissingAtlas(Sheet, &'static str),
+    MissingClip(Sheet, &'static str),
+}
+
+impl fmt::Display for SpriteSheetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpriteSheetError::Ron(err) => write!(f, "{err}"),
+            SpriteSheetError::EmptyAtlas(atlas) => write!(f, "the {atlas} atlas has no tiles"),
+            SpriteSheetError::EmptyClip(clip) => write!(f, "the {clip} clip has no frames"),
+            SpriteSheetError::InvalidFps(clip) => {
+                write!(f, "the {clip} clip needs a positive fps")
+            }
+            SpriteSheetError::UnknownAtlas { clip, atlas } => {
+                write!(f, "the {clip} clip plays the unknown {atlas} atlas")
+            }
+            SpriteSheetError::OutOfAtlas(clip) => {
+                write!(f, "the {clip} clip goes past the last tile of its atlas")
+            }
+            SpriteSheetError::UnknownNextClip { clip, next } => {
+                write!(f, "the {clip} clip goes on with the unknown {next} clip")
+            }
+            SpriteSheetError::UnknownTransitionClip(clip) => {
+                write!(f, "a transition goes to the unknown {clip} clip")
+            }
+            SpriteSheetError::MissingAtlas(sheet, atlas) => {
+                write!(f, "the {sheet} sheet needs a {atlas} atlas")
+            }
+            SpriteSheetError::MissingClip(sheet, clip) => {
+                write!(f, "the {sheet} sheet needs a {clip} clip")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SpriteSheetError {}
+
+impl From<ron::error::SpannedError> for SpriteSheetError {
+    fn from(err: ron::error::SpannedError) -> Self {
+        SpriteSheetError::Ron(err)
+    }
+}
+
+impl SpriteSheet {
+    /// Reads and checks a sheet, along with what the game asks of it when it is one of its
+    /// [`Sheet`]s.
+    pub fn parse(bytes: &[u8], sheet: Option<Sheet>) -> Result<SpriteSheet, SpriteSheetError> {
+        let sprite_sheet: SpriteSheet = ron::de::from_bytes(bytes)?;
+        sprite_sheet.validate()?;
+        if let Some(sheet) = sheet {
+            let (atlases, clips) = sheet.required();
+            if let Some(atlas) = atlases
+                .iter()
+                .find(|atlas| !sprite_sheet.atlases.contains_key(**atlas))
+            {
+                return Err(SpriteSheetError::MissingAtlas(sheet, atlas));
+            }
+            if let Some(clip) = clips
+                .iter()
+                .find(|clip| !sprite_sheet.clips.contains_key(**clip))
+            {
+                return Err(SpriteSheetError::MissingClip(sheet, clip));
+            }
+        }
+        Ok(sprite_sheet)
+    }
+
+    /// Checks that every clip plays frames of its atlas, and that clips and transitions go on
+    /// with clips of the sheet.
+    pub fn validate(&self) -> Result<(), SpriteSheetError> {
+        for (name, atlas) in &self.atlases {
+            if atlas.columns * atlas.rows == 0 || atlas.tile_size.min_element() <= 0. {
+                return Err(SpriteSheetError::EmptyAtlas(name.clone()));
+            }
+        }
+        for (name, clip) in &self.clips {
+            if clip.len == 0 {
+                return Err(SpriteSheetError::EmptyClip(name.clone()));
+            }
+            if !(clip.fps.is_finite() && clip.fps > 0.) {
+                return Err(SpriteSheetError::InvalidFps(name.clone()));
+            }
+            let Some(atlas) = self.atlases.get(&clip.atlas) else {
+                return Err(SpriteSheetError::UnknownAtlas {
+                    clip: name.clone(),
+                    atlas: clip.atlas.clone(),
+                });
+            };
+            if clip.first + clip.len > atlas.columns * atlas.rows {
+                return Err(SpriteSheetError::OutOfAtlas(name.clone()));
+            }
+            if let PlayMode::Once { next: Some(next) } = &clip.mode {
+                if !self.clips.contains_key(next) {
+                    return Err(SpriteSheetError::UnknownNextClip {
+                        clip: name.clone(),
+                        next: next.clone(),
+                    });
+                }
+            }
+        }
+        if let Some(transition) = self
+            .transitions
+            .iter()
+            .find(|transition| !self.clips.contains_key(&transition.to))
+        {
+            return Err(SpriteSheetError::UnknownTransitionClip(
+                transition.to.clone(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct SpriteSheetLoader;
+
+impl AssetLoader for SpriteSheetLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let sheet = SHEETS
+                .into_iter()
+                .find(|sheet| load_context.path() == std::path::Path::new(sheet.path()));
+            let sprite_sheet = SpriteSheet::parse(bytes, sheet)?;
+            load_context.set_default_asset(LoadedAsset::new(sprite_sheet));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["sheet.ron"]
+    }
+}
+
+/// The sheets of the game, loaded on startup, and the shipped ones for until they are.
+#[derive(Resource)]
+pub struct SpriteSheets {
+    handles: HashMap<Sheet, Handle<SpriteSheet>>,
+    builtin: HashMap<Sheet, SpriteSheet>,
+}
+
+impl FromWorld for SpriteSheets {
+    fn from_world(world: &mut World) -> Self {
+        let handles = world
+            .get_resource::<AssetServer>()
+            .map(|asset_server| {
+                SHEETS
+                    .into_iter()
+                    .map(|sheet| (sheet, asset_server.load(sheet.path())))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let builtin = SHEETS
+            .into_iter()
+            .map(|sheet| {
+                let sprite_sheet = SpriteSheet::parse(sheet.builtin().as_bytes(), Some(sheet))
+                    .unwrap_or_else(|err| panic!("The shipped {sheet} sheet is invalid: {err}"));
+                (sheet, sprite_sheet)
+            })
+            .collect();
+        SpriteSheets { handles, builtin }
+    }
+}
+
+pub struct SpritesPlugin;
+
+impl Plugin for SpritesPlugin {
+    fn build(&self, app: &mut App) {
+        // Headless, the sheets are not loaded and every entity uses the shipped ones
+        if app.world.contains_resource::<AssetServer>() {
+            app.add_asset::<SpriteSheet>()
+                .init_asset_loader::<SpriteSheetLoader>();
+        }
+        app.init_resource::<SpriteSheets>().add_system(
+            check_atlas_images
+                .run_if(resource_exists::<AssetServer>())
+                .run_if(resource_exists::<Assets<Image>>()),
+        );
+    }
+}
+
+/// Warns about the atlases cut past the edges of their image, once it is loaded, since
+/// the size of the image is only known then.
+fn check_atlas_images(
+    mut events: EventReader<AssetEvent<Image>>,
+    images: Res<Assets<Image>>,
+    asset_server: Res<AssetServer>,
+    sprites: Sprites,
+) {
+    for event in events.iter() {
+        let (AssetEvent::Created { handle } | AssetEvent::Modified { handle }) = event else {
+            continue;
+        };
+        let Some(image) = images.get(handle) else {
+            continue;
+        };
+        for sheet in SHEETS {
+            for (name, atlas) in &sprites.sheet(sheet).atlases {
+                if asset_server.get_handle::<Image, _>(atlas.image.as_str()) != *handle {
+                    continue;
+                }
+                if !atlas.fits(image.size()) {
+                    warn!(
+                        "The {name} atlas of the {sheet} sheet is cut past the edges of {}",
+                        atlas.image
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Loads the sprites of the gameplay entities. Without an [`AssetServer`], when the
+/// simulation runs headless, the handles are left empty and the rest runs the same.
+#[derive(SystemParam)]
+pub struct Sprites<'w> {
+    asset_server: Option<Res<'w, AssetServer>>,
+    texture_atlases: Option<ResMut<'w, Assets<TextureAtlas>>>,
+    sheets: Res<'w, SpriteSheets>,
+    sheet_assets: Option<Res<'w, Assets<SpriteSheet>>>,
+}
+
+impl Sprites<'_> {
+    pub fn image(&self, path: &str) -> Handle<Image> {
+        self.asset_server
+            .as_ref()
+            .map_or_else(Handle::default, |asset_server| asset_server.load(path))
+    }
+
+    /// A texture atlas cut from the image in a grid of `columns` by `rows` tiles.
+    pub fn atlas(
+        &mut self,
+        path: &str,
+        tile_size: Vec2,
+        columns: usize,
+        rows: usize,
+    ) -> Handle<TextureAtlas> {
+        let image = self.image(path);
+        self.texture_atlases
+            .as_mut()
+            .map_or_else(Handle::default, |texture_atlases| {
+                texture_atlases.add(TextureAtlas::from_grid(
+                    image, tile_size, columns, rows, None, None,
+                ))
+            })
+    }
+
+    /// The loaded sheet, or the shipped one while it is not loaded.
+    pub fn sheet(&self, sheet: Sheet) -> &SpriteSheet {
+        self.sheet_assets
+            .as_ref()
+            .zip(self.sheets.handles.get(&sheet))
+            .and_then(|(assets, handle)| assets.get(handle))
+            .unwrap_or(&self.sheets.builtin[&sheet])
+    }
+
+    /// The atlas of the sheet with the given name.
+    pub fn sheet_atlas(&mut self, sheet: Sheet, name: &str) -> Handle<TextureAtlas> {
+        let Some(atlas) = self.sheet(sheet).atlases.get(name).cloned() else {
+            warn!("The {sheet} sheet has no {name} atlas");
+            return Handle::default();
+        };
+        self.atlas(&atlas.image, atlas.tile_size, atlas.columns, atlas.rows)
+    }
+
+    /// The transitions of the sheet, in the order they are tried.
+    pub fn transitions(&self, sheet: Sheet) -> Vec<Transition> {
+        self.sheet(sheet).transitions.clone()
+    }
+
+    /// Every clip of the sheet, each atlas cut once for all the clips playing it.
+    pub fn clips(&mut self, sheet: Sheet) -> Clips {
+        let sprite_sheet = self.sheet(sheet).clone();
+        let atlases: HashMap<&str, Handle<TextureAtlas>> = sprite_sheet
+            .atlases
+            .iter()
+            .map(|(name, atlas)| {
+                let handle = self.atlas(&atlas.image, atlas.tile_size, atlas.columns, atlas.rows);
+                (name.as_str(), handle)
+            })
+            .collect();
+        Clips::new(sprite_sheet.clips.iter().map(|(name, clip)| {
+            (
+                name.as_str(),
+                Clip {
+                    atlas: atlases[clip.atlas.as_str()].clone(),
+                    first: clip.first,
+                    len: clip.len,
+                    fps: clip.fps,
+                    mode: clip.mode.clone(),
+                },
+            )
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(clips: &str, sheet: Option<Sheet>) -> Result<SpriteSheet, SpriteSheetError> {
+        let ron = format!(
+            r#"(
+                atlases: {{
+                    "glow": (image: "glow.png", tile_size: (8, 8), columns: 2, rows: 2),
+                }},
+                clips: {{ {clips} }},
+            )"#
+        );
+        SpriteSheet::parse(ron.as_bytes(), sheet)
+    }
+
+    #[test]
+    fn shipped_sheets_are_valid() {
+        for sheet in SHEETS {
+            SpriteSheet::parse(sheet.builtin().as_bytes(), Some(sheet)).unwrap();
+        }
+    }
+
+    #[test]
+    fn accepts_clips_within_their_atlas() {
+        let sheet = parse(
+            r#""glow": (atlas: "glow", first: 1, len: 3, fps: 4, mode: Once(next: Some("idle"))),
+               "idle": (atlas: "glow", len: 1, fps: 1),"#,
+            Some(Sheet::Wall),
+        )
+        .unwrap();
+        assert_eq!(sheet.clips["glow"].first, 1);
+    }
+
+    #[test]
+    fn rejects_invalid_sheets() {
+        assert!(matches!(parse("(", None), Err(SpriteSheetError::Ron(_))));
+        assert!(matches!(
+            SpriteSheet::parse(
+                br#"(atlases: { "a": (image: "a.png", tile_size: (8, 8), columns: 0, rows: 1) })"#,
+                None
+            ),
+            Err(SpriteSheetError::EmptyAtlas(_))
+        ));
+        assert!(matches!(
+            parse(r#""a": (atlas: "glow", len: 0, fps: 4),"#, None),
+            Err(SpriteSheetError::EmptyClip(_))
+        ));
+        assert!(matches!(
+            parse(r#""a": (atlas: "glow", len: 1, fps: 0),"#, None),
+            Err(SpriteSheetError::InvalidFps(_))
+        ));
+        assert!(matches!(
+            parse(r#""a": (atlas: "run", len: 1, fps: 4),"#, None),
+            Err(SpriteSheetError::UnknownAtlas { .. })
+        ));
+        assert!(matches!(
+            parse(r#""a": (atlas: "glow", first: 2, len: 3, fps: 4),"#, None),
+            Err(SpriteSheetError::OutOfAtlas(_))
+        ));
+        assert!(matches!(
+            parse(
+                r#""a": (atlas: "glow", len: 1, fps: 4, mode: Once(next: Some("b"))),"#,
+                None
+            ),
+            Err(SpriteSheetError::UnknownNextClip { .. })
+        ));
+        assert!(matches!(
+            SpriteSheet::parse(
+                br#"(atlases: {}, transitions: [(to: "run", when: [Above("speed", 0)])])"#,
+                None
+            ),
+            Err(SpriteSheetError::UnknownTransitionClip(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_sheets_missing_what_the_game_asks_for() {
+        assert!(matches!(
+            parse("", Some(Sheet::Wall)),
+            Err(SpriteSheetError::MissingClip(Sheet::Wall, "glow"))
+        ));
+        assert!(matches!(
+            parse("", Some(Sheet::Platform)),
+            Err(SpriteSheetError::MissingAtlas(Sheet::Platform, "platform"))
+        ));
+    }
+
+    #[test]
+    fn atlas_fits_its_image() {
+        let atlas = AtlasDefinition {
+            image: "glow.png".into(),
+            tile_size: Vec2::new(80., 368.),
+            columns: 4,
+            rows: 1,
+        };
+        assert!(atlas.fits(Vec2::new(320., 368.)));
+        assert!(!atlas.fits(Vec2::new(320., 360.)));
+        assert!(!atlas.fits(Vec2::new(240., 368.)));
+    }
+}
@@ -1,12 +1,19 @@
-use bevy::{prelude::*, utils::Instant, window::PrimaryWindow};
+use std::time::Duration;
+
+use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
-use rand::{random, thread_rng, Rng};
+use rand::Rng;
 
 use crate::{
-    animation::AnimationTimer,
-    effects::{Effect, EffectQueue},
-    player::LastWall,
-    AppState, Score, Wall,
+    animation::Animator,
+    difficulty::Difficulty,
+    effects::{ActiveEffects, Effect},
+    physics::{Arena, Interpolated},
+    player::{LastWall, Player},
+    reachability::{is_reachable, reaches_wall, Foothold, JumpArc, PlatformSpan},
+    rng::PlatformRng,
+    sprites::{Sheet, Sprites},
+    AppState, GameplaySet, Score, Wall,
 };
 
 pub struct TilesPlugin;
@@ -15,72 +22,127 @@ pub struct TilesPlugin;
 pub struct Platform;
 #[derive(Component)]
 pub struct FirstPlatform;
+/// Keeps the player from jumping out of the top of the arena.
+#[derive(Component)]
+pub struct Ceiling;
+
+/// Multiplier applied to the speed of every platform. Faster platforms are also emitted
+/// more often, so the distance between two consecutive platforms stays the same.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct PlatformSpeed {
+    /// Set by the active side effects.
+    pub effects: f32,
+}
+
+impl Default for PlatformSpeed {
+    fn default() -> Self {
+        PlatformSpeed { effects: 1. }
+    }
+}
+
+/// The slowest and fastest the platforms go, so the time between two of them stays
+/// positive and finite.
+const MIN_PLATFORM_SPEED: f32 = 0.05;
+const MAX_PLATFORM_SPEED: f32 = 20.;
 
+impl PlatformSpeed {
+    pub fn value(&self) -> f32 {
+        if self.effects.is_nan() {
+            return MIN_PLATFORM_SPEED;
+        }
+        self.effects.clamp(MIN_PLATFORM_SPEED, MAX_PLATFORM_SPEED)
+    }
+}
+
+/// The velocity of a platform before [`PlatformSpeed`] is applied.
+#[derive(Component, Deref, DerefMut)]
+pub struct BaseVelocity(pub Vec2);
+
+/// Emits a platform every time it finishes, ticked on every fixed step.
+/// Its duration follows the [`PlatformSpeed`].
 #[derive(Resource, DerefMut, Deref)]
-pub struct PlatformTimer(pub Instant);
+pub struct PlatformTimer(pub Timer);
+
+impl Default for PlatformTimer {
+    fn default() -> Self {
+        PlatformTimer(Timer::from_seconds(
+            PLATFORM_BASE_INTERVAL_SECS,
+            TimerMode::Repeating,
+        ))
+    }
+}
+
+/// The last platform emitted with a collider, which the next one has to be reachable from.
+#[derive(Resource, Default)]
+pub struct LastSolidPlatform(Option<SolidPlatform>);
+
+struct SolidPlatform {
+    entity: Entity,
+    half_width: f32,
+    /// Height of the top of its collider.
+    top: f32,
+    /// The score when it was emitted, platforms of another crossing don't lead to the wall.
+    score: usize,
+}
 
 impl Plugin for TilesPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(spawn_obstacles.in_schedule(OnEnter(AppState::InGame)))
+        app.init_resource::<PlatformSpeed>()
+            .add_system(spawn_obstacles.in_schedule(OnEnter(AppState::InGame)))
+            .add_system(highlight_target_wall.in_set(OnUpdate(AppState::InGame)))
             .add_systems(
                 (
-                    highlight_target_wall,
                     emit_platforms,
-                    apply_icy_platforms,
-                    despawn_out_of_screen_platforms.after(apply_icy_platforms),
+                    change_platform_velocity,
+                    despawn_out_of_screen_platforms,
                 )
-                    .in_set(OnUpdate(AppState::InGame)),
+                    .in_set(GameplaySet::Simulation)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(
+                apply_icy_platforms
+                    .in_set(GameplaySet::Events)
+                    .in_schedule(CoreSchedule::FixedUpdate),
             )
             .add_systems(
-                (despawn_obstacles::<Wall>, despawn_obstacles::<Platform>)
+                (
+                    despawn_obstacles::<Wall>,
+                    despawn_obstacles::<Ceiling>,
+                    despawn_obstacles::<Platform>,
+                )
                     .in_schedule(OnExit(AppState::InGame)),
             );
     }
 }
 
-fn spawn_obstacles(
-    mut commands: Commands,
-    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
-    asset_server: Res<AssetServer>,
-    window_query: Query<&Window, With<PrimaryWindow>>,
-) {
-    let Ok(window) = window_query.get_single() else {
-        return;
-    };
-
-    let wall_width = 80.;
-    let wall_height = window.height();
+fn spawn_obstacles(mut commands: Commands, mut sprites: Sprites, arena: Res<Arena>) {
+    let wall_height = arena.height;
 
     // Background
     commands.spawn(SpriteBundle {
         sprite: Sprite {
-            custom_size: Some(Vec2::new(window.width(), window.height())),
+            custom_size: Some(Vec2::new(arena.width, arena.height)),
             ..default()
         },
-        texture: asset_server.load("textures/BG.png"),
-        transform: Transform::from_xyz(window.width() / 2., window.height() / 2., 0.),
+        texture: sprites.image("textures/BG.png"),
+        transform: Transform::from_xyz(arena.width / 2., arena.height / 2., 0.),
         ..default()
     });
 
-    // Walls
+    // Walls, their glow stretched to the height of the arena
+    let glow = sprites.clips(Sheet::Wall);
+    let glow_width = sprites.sheet(Sheet::Wall).atlases["glow"].tile_size.x;
     [0., wall_height].into_iter().for_each(|y| {
-        [(-5., Wall::Left), (5. + window.width(), Wall::Right)]
+        [(-5., Wall::Left), (5. + arena.width, Wall::Right)]
             .into_iter()
             .for_each(|(x, wall)| {
                 commands
                     .spawn(RigidBody::Fixed)
                     .insert(SpriteSheetBundle {
-                        texture_atlas: texture_atlases.add(TextureAtlas::from_grid(
-                            asset_server.load("textures/sideglow.png"),
-                            Vec2::new(wall_width, wall_height),
-                            4,
-                            1,
-                            None,
-                            None,
-                        )),
                         sprite: TextureAtlasSprite {
                             index: 0,
                             flip_x: Wall::Right == wall,
+                            custom_size: Some(Vec2::new(glow_width, wall_height)),
                             color: if Wall::Left == wall {
                                 Color::GREEN
                             } else {
@@ -94,16 +156,25 @@ fn spawn_obstacles(
                     .insert(Collider::cuboid(10., wall_height / 2.))
                     .insert(ColliderMassProperties::Density(f32::INFINITY))
                     .insert(wall)
-                    .insert(AnimationTimer(Timer::from_seconds(
-                        1. / 4.,
-                        TimerMode::Repeating,
-                    )));
+                    .insert(Animator::new(glow.clone(), "glow"));
             });
     });
 
+    // Ceiling, just above the arena and as wide as the walls are apart
+    commands
+        .spawn(RigidBody::Fixed)
+        .insert(TransformBundle::from(Transform::from_xyz(
+            arena.width / 2.,
+            arena.height + 10.,
+            0.,
+        )))
+        .insert(Collider::cuboid(arena.width / 2. + 10., 10.))
+        .insert(Ceiling);
+
     // Starting platform
     commands
         .spawn(RigidBody::KinematicVelocityBased)
+        .insert(Interpolated::default())
         .insert(Collider::cuboid(
             PLATFORM_MIN_WIDTH / 2.,
             PLATFORM_SPRITE_SIZE / 2.,
@@ -113,91 +184,180 @@ fn spawn_obstacles(
                 custom_size: Some(Vec2::new(PLATFORM_MIN_WIDTH, PLATFORM_SPRITE_SIZE)),
                 ..default()
             },
-            texture: asset_server.load("sprites/platform.png"),
+            texture: sprites.image("sprites/platform.png"),
             transform: Transform::from_xyz(PLATFORM_SPRITE_SIZE * 2., PLATFORM_MIN_Y, 1.),
             ..default()
         })
         .insert(Platform)
         .insert(FirstPlatform)
-        .insert(Velocity::linear(Vec2::new(-10., 0.)));
+        .insert(Velocity::linear(Vec2::new(-10., 0.)))
+        .insert(BaseVelocity(Vec2::new(-10., 0.)));
 
-    commands.insert_resource(PlatformTimer(Instant::now()));
+    commands.insert_resource(PlatformTimer::default());
+    commands.insert_resource(LastSolidPlatform::default());
 }
 
 const PLATFORM_START_WIDTH: f32 = 250.;
 const PLATFORM_SPRITE_SIZE: f32 = 32.;
 const PLATFORM_MIN_WIDTH: f32 = 3. * PLATFORM_SPRITE_SIZE;
 const PLATFORM_MIN_Y: f32 = 150.;
+/// Height of the top of the starting platform.
+const START_PLATFORM_TOP: f32 = PLATFORM_MIN_Y + PLATFORM_SPRITE_SIZE / 2.;
+/// The walls reach down to the bottom of the arena.
+const WALL_BOTTOM: f32 = 0.;
+/// Half the height of the collider of an emitted platform.
+const PLATFORM_COLLIDER_HALF_HEIGHT: f32 = (PLATFORM_SPRITE_SIZE - 15.) / 2.;
+const MAX_PLATFORM_PARTS: usize = 4;
+/// How much an unreachable platform is lowered at a time.
+const REACHABILITY_LOWERING_STEP: f32 = 4.;
+const PLATFORM_BASE_INTERVAL_SECS: f32 = 1.5;
+/// How quickly on-screen platforms catch up with a new [`PlatformSpeed`]
+const PLATFORM_SPEED_CHANGE_RATE: f32 = 3.;
+
+/// Half the width of the collider of a platform made of `plat_num` parts.
+fn platform_half_width(plat_num: usize) -> f32 {
+    let collider_width = if plat_num == 1 {
+        PLATFORM_SPRITE_SIZE * 2.
+    } else {
+        PLATFORM_MIN_WIDTH + ((plat_num - 1) as f32) * PLATFORM_SPRITE_SIZE * 2.
+    };
+    (collider_width - PLATFORM_SPRITE_SIZE * 1.2) / 2.
+}
 
 fn emit_platforms(
     mut commands: Commands,
-    timer: Res<PlatformTimer>,
+    fixed_time: Res<FixedTime>,
+    rapier_config: Res<RapierConfiguration>,
+    mut timer: ResMut<PlatformTimer>,
+    mut last_solid: ResMut<LastSolidPlatform>,
     score: Res<Score>,
-    effect_q: Res<EffectQueue>,
-    asset_server: Res<AssetServer>,
+    difficulty: Res<Difficulty>,
+    mut rng: ResMut<PlatformRng>,
+    platform_speed: Res<PlatformSpeed>,
+    active_effects: Res<ActiveEffects>,
+    mut sprites: Sprites,
+    arena: Res<Arena>,
     last_wall_query: Query<&LastWall>,
-    window_query: Query<&Window, With<PrimaryWindow>>,
-    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    gravity_query: Query<&GravityScale, With<Player>>,
+    span_query: Query<(&Transform, &Velocity)>,
 ) {
-    let Ok(window) = window_query.get_single() else {
-        return;
-    };
-    let (plat_velocity, delta_secs) = if let Some(Effect::FastPlatforms) = effect_q.last() {
-        (250., 0.75)
-    } else {
-        (125., 1.5)
-    };
-    // Spawn a platform every second
-    if timer.elapsed().as_secs_f32() < delta_secs {
+    // Faster platforms come more often, so they stay as far apart
+    let interval = Duration::from_secs_f32(PLATFORM_BASE_INTERVAL_SECS / platform_speed.value());
+    if timer.duration() != interval {
+        timer.set_duration(interval);
+    }
+    if !timer.tick(fixed_time.period).just_finished() {
         return;
     }
-    // Reset timer
-    commands.insert_resource(PlatformTimer(Instant::now()));
 
-    // Platforms should get smaller as the score increases
-    let mut rng = thread_rng();
-    let max_plat_parts = match score.0 {
-        0..=5 => 4,
-        6..=9 => 3,
-        10..=15 => 2,
-        _ => 1,
-    };
-    let plat_num = rng.gen_range(1..=max_plat_parts) as usize;
-
-    let platform_height = PLATFORM_SPRITE_SIZE;
-    let platform_y = PLATFORM_MIN_Y + random::<f32>() * (window.height() / 15.);
-    let (platform_x, velocity) = match last_wall_query.get_single() {
-        Ok(LastWall(Wall::Left)) => (
-            window.width() + PLATFORM_START_WIDTH,
-            Vec2::new(-(random::<f32>() * 5. + plat_velocity), 0.),
-        ),
-        Ok(LastWall(Wall::Right)) => (
-            -PLATFORM_START_WIDTH,
-            Vec2::new(random::<f32>() * 5. + plat_velocity, 0.),
-        ),
+    let (platform_x, direction) = match last_wall_query.get_single() {
+        Ok(LastWall(Wall::Left)) => (arena.width + PLATFORM_START_WIDTH, -1.),
+        Ok(LastWall(Wall::Right)) => (-PLATFORM_START_WIDTH, 1.),
         Err(_) => return,
     };
 
+    // Platforms should get smaller as the score increases
+    let curves = &difficulty.curves;
+    let max_plat_parts = curves.max_platform_parts(score.0);
+    let mut plat_num = rng.gen_range(1..=max_plat_parts) as usize;
+
+    let mut platform_y =
+        PLATFORM_MIN_Y + rng.gen::<f32>() * curves.platform_height_range.at(score.0);
+    let velocity = Vec2::new(
+        direction
+            * (rng.gen::<f32>() * curves.platform_speed_variation.at(score.0)
+                + curves.platform_speed.at(score.0)),
+        0.,
+    );
+
     // Apply side effects..
-    let is_fallthrough =
-        effect_q.last() == Some(&Effect::FallthroughPlatforms) && random::<f32>() < 0.25;
+    // Always roll, so the platforms drawn later do not depend on the active effects
+    let fallthrough_roll = rng.gen::<f32>();
+    let mut is_fallthrough =
+        active_effects.contains(&Effect::FallthroughPlatforms) && fallthrough_roll < 0.25;
+    let is_icy = active_effects.contains(&Effect::IcyPlatforms);
+
+    // Keep a way to the target wall, from the last platform of this crossing to stand on,
+    // or from the wall the player comes from when there is none yet
+    let foothold = match last_solid.0.as_ref() {
+        Some(solid) if solid.score == score.0 => {
+            span_query
+                .get(solid.entity)
+                .ok()
+                .map(|(transform, velocity)| {
+                    Foothold::Platform(PlatformSpan {
+                        x: transform.translation.x,
+                        top: solid.top,
+                        half_width: solid.half_width,
+                        speed: velocity.linvel.x.abs(),
+                    })
+                })
+        }
+        // The player was left at the wall by the last platform of the previous crossing
+        solid => Some(Foothold::Wall(
+            solid.map_or(START_PLATFORM_TOP, |solid| solid.top),
+        )),
+    };
+    if let (Some(from), Ok(gravity_scale)) = (foothold, gravity_query.get_single()) {
+        let jump = JumpArc::new(rapier_config.gravity.y, gravity_scale.0);
+        let speed = velocity.x.abs() * platform_speed.value();
+        // Both platforms are in play until the new one has crossed the screen
+        let lifetime = (arena.width + PLATFORM_START_WIDTH) / speed;
+        let span = |x: f32, plat_num: usize, platform_y: f32| PlatformSpan {
+            x,
+            top: platform_y + PLATFORM_COLLIDER_HALF_HEIGHT,
+            half_width: platform_half_width(plat_num),
+            speed,
+        };
+
+        // Falling through this platform, the player has to make it to the next one,
+        // so it has to be within reach even with the best platform to come
+        if is_fallthrough {
+            let spacing = PLATFORM_BASE_INTERVAL_SECS * velocity.x.abs();
+            let best_next = span(
+                platform_x - direction * spacing,
+                MAX_PLATFORM_PARTS,
+                PLATFORM_MIN_Y,
+            );
+            is_fallthrough = is_reachable(&jump, &from, &best_next, lifetime);
+        }
+        let reachable = |plat_num: usize, platform_y: f32| {
+            let to = span(platform_x, plat_num, platform_y);
+            is_reachable(&jump, &from, &to, lifetime)
+        };
+        // Standing on it, the player has to be able to make the final hop to the wall,
+        // which a wider platform carries further away
+        let leads_to_wall = |plat_num: usize, platform_y: f32| {
+            reaches_wall(&jump, &span(platform_x, plat_num, platform_y), WALL_BOTTOM)
+        };
+        while !is_fallthrough && plat_num > 1 && !leads_to_wall(plat_num, platform_y) {
+            plat_num -= 1;
+        }
+        // Lower the platform first, then widen it
+        while !is_fallthrough && !reachable(plat_num, platform_y) {
+            if platform_y > PLATFORM_MIN_Y {
+                platform_y = (platform_y - REACHABILITY_LOWERING_STEP).max(PLATFORM_MIN_Y);
+            } else if plat_num < MAX_PLATFORM_PARTS && leads_to_wall(plat_num + 1, platform_y) {
+                plat_num += 1;
+            } else {
+                warn!("Could not make the platform reachable");
+                break;
+            }
+        }
+        if !is_fallthrough && !leads_to_wall(plat_num, platform_y) {
+            warn!("Could not make the wall reachable from the platform");
+        }
+    }
 
     let color = if is_fallthrough {
         Color::rgba(1., 1., 1., 0.5)
-    } else if effect_q.last() == Some(&Effect::IcyPlatforms) {
+    } else if is_icy {
         Color::rgb(0., 0.2, 0.9)
     } else {
         Color::WHITE
     };
 
-    let atlas = texture_atlases.add(TextureAtlas::from_grid(
-        asset_server.load("sprites/platform.png"),
-        Vec2::new(PLATFORM_SPRITE_SIZE, PLATFORM_SPRITE_SIZE),
-        3,
-        1,
-        None,
-        None,
-    ));
+    let atlas = sprites.sheet_atlas(Sheet::Platform, "platform");
 
     let spawn_platform_sprite = |parent: &mut ChildBuilder, index: usize, x: f32| {
         parent.spawn((
@@ -217,6 +377,7 @@ fn emit_platforms(
 
     let entity = commands
         .spawn(RigidBody::KinematicVelocityBased)
+        .insert(Interpolated::default())
         .insert(SpriteSheetBundle {
             sprite: TextureAtlasSprite {
                 index: 1,
@@ -236,7 +397,8 @@ fn emit_platforms(
             coefficient: 0.,
             combine_rule: CoefficientCombineRule::Min,
         })
-        .insert(Velocity::linear(velocity))
+        .insert(Velocity::linear(velocity * platform_speed.value()))
+        .insert(BaseVelocity(velocity))
         .insert(Platform)
         .with_children(|parent| {
             for i in 1..plat_num {
@@ -258,56 +420,62 @@ fn emit_platforms(
         .id();
 
     if !is_fallthrough {
-        let collider_width = if plat_num == 1 {
-            PLATFORM_SPRITE_SIZE * 2.
-        } else {
-            PLATFORM_MIN_WIDTH + ((plat_num - 1) as f32) * PLATFORM_SPRITE_SIZE * 2.
-        };
-        commands.entity(entity).insert(Collider::cuboid(
-            (collider_width - PLATFORM_SPRITE_SIZE * 1.2) / 2.,
-            (platform_height - 15.) / 2.,
-        ));
+        let half_width = platform_half_width(plat_num);
+        commands
+            .entity(entity)
+            .insert(Collider::cuboid(half_width, PLATFORM_COLLIDER_HALF_HEIGHT));
+        last_solid.0 = Some(SolidPlatform {
+            entity,
+            half_width,
+            top: platform_y + PLATFORM_COLLIDER_HALF_HEIGHT,
+            score: score.0,
+        });
     }
-    if effect_q.last() == Some(&Effect::IcyPlatforms) {
+    if is_icy {
         commands.entity(entity).insert(Icy);
     }
 }
 
+fn change_platform_velocity(
+    fixed_time: Res<FixedTime>,
+    platform_speed: Res<PlatformSpeed>,
+    mut platform_query: Query<(&BaseVelocity, &mut Velocity), With<Platform>>,
+) {
+    let t = 1. - (-PLATFORM_SPEED_CHANGE_RATE * fixed_time.period.as_secs_f32()).exp();
+    for (base_velocity, mut velocity) in platform_query.iter_mut() {
+        let target = base_velocity.0 * platform_speed.value();
+        velocity.linvel = velocity.linvel.lerp(target, t);
+    }
+}
+
 fn despawn_out_of_screen_platforms(
     mut commands: Commands,
     query: Query<(Entity, &Transform), With<Platform>>,
-    window_query: Query<&Window, With<PrimaryWindow>>,
+    arena: Res<Arena>,
 ) {
-    let Ok(window) = window_query.get_single() else {
-        return;
-    };
     for (entity, transform) in query.iter() {
-        if transform.translation.x < -500. || transform.translation.x > window.width() + 500. {
+        if transform.translation.x < -500. || transform.translation.x > arena.width + 500. {
             commands.entity(entity).despawn_recursive();
         }
     }
 }
 
 fn highlight_target_wall(
-    time: Res<Time>,
-    mut wall_query: Query<(&Wall, &mut AnimationTimer, &mut TextureAtlasSprite), With<Wall>>,
+    mut wall_query: Query<(&Wall, &mut Animator, &mut TextureAtlasSprite), With<Wall>>,
     last_wall_query: Query<&LastWall>,
 ) {
     let Ok(last_wall)= last_wall_query.get_single() else {
       return;
     };
-    for (wall, mut timer, mut sprite) in &mut wall_query {
+    for (wall, mut animator, mut sprite) in &mut wall_query {
         // Animate the target wall, and set the other wall to be transparent
         if wall == &last_wall.0 {
             sprite.color.set_a(0.);
-            timer.reset();
-            sprite.index = 0;
+            animator.paused = true;
+            animator.seek(0);
         } else {
             sprite.color.set_a(1.);
-            timer.tick(time.delta());
-            if timer.just_finished() {
-                sprite.index = (sprite.index + 1) % 4;
-            }
+            animator.paused = false;
         }
     }
 }
@@ -317,17 +485,17 @@ fn highlight_target_wall(
 pub struct Icy;
 
 fn apply_icy_platforms(
-    effect_q: Res<EffectQueue>,
+    active_effects: Res<ActiveEffects>,
     mut commands: Commands,
     mut platform_query: Query<
         (Entity, Option<&mut Sprite>, Option<&mut TextureAtlasSprite>),
         With<Platform>,
     >,
 ) {
-    if !effect_q.is_changed() {
+    if !active_effects.is_changed() {
         return;
     }
-    let color = if let Some(Effect::IcyPlatforms) = effect_q.last() {
+    let color = if active_effects.contains(&Effect::IcyPlatforms) {
         Color::rgb(0.0, 0.2, 0.9)
     } else {
         Color::WHITE
@@ -356,3 +524,19 @@ fn despawn_obstacles<T: Component>(mut commands: Commands, mut query: Query<Enti
         commands.entity(entity).despawn_recursive();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn platform_speed_stays_positive_and_finite() {
+        for effects in [0., -1., f32::NAN, f32::INFINITY] {
+            let speed = PlatformSpeed { effects }.value();
+            assert!(speed > 0. && speed.is_finite(), "{effects} gives {speed}");
+            let interval = Duration::from_secs_f32(PLATFORM_BASE_INTERVAL_SECS / speed);
+            assert!(interval > Duration::ZERO);
+        }
+        assert_eq!(PlatformSpeed { effects: 0.5 }.value(), 0.5);
+    }
+}
@@ -0,0 +1,180 @@
+use bevy::prelude::*;
+
+use crate::{
+    actions::{Action, KeyboardCaptured, ACTIONS},
+    settings::KeyBindings,
+    AppState,
+};
+
+use super::{spawn_button, spawn_sized_button, ButtonAction};
+
+#[derive(Component)]
+pub struct ControlsScreen;
+
+/// The action waiting for a key to be bound to it.
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct Rebinding(pub Option<Action>);
+
+const TITLE_TEXT: &str = "Controls";
+/// Smaller than the other buttons, for long key names to fit two columns.
+const BINDING_FONT_SIZE: f32 = 24.;
+
+fn binding_label(action: Action, key_bindings: &KeyBindings, rebinding: &Rebinding) -> String {
+    if rebinding.0 == Some(action) {
+        format!("{action}: press a key")
+    } else {
+        format!("{action}: {:?}", key_bindings.key(action))
+    }
+}
+
+pub fn spawn_controls(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    key_bindings: Res<KeyBindings>,
+) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let rebinding = Rebinding::default();
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: BackgroundColor(Color::DARK_GRAY),
+                ..default()
+            },
+            ControlsScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn((TextBundle {
+                        style: Style {
+                            margin: UiRect::vertical(Val::Px(12.)),
+                            align_self: AlignSelf::Center,
+                            ..default()
+                        },
+                        text: Text::from_section(
+                            TITLE_TEXT,
+                            TextStyle {
+                                font: font.clone(),
+                                font_size: 48.0,
+                                color: Color::CRIMSON,
+                            },
+                        ),
+                        ..default()
+                    },));
+                    // The bindings in two columns, so the screen fits the window
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                justify_content: JustifyContent::Center,
+                                ..default()
+                            },
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            let (left, right) = ACTIONS.split_at((ACTIONS.len() + 1) / 2);
+                            for column in [left, right] {
+                                parent
+                                    .spawn(NodeBundle {
+                                        style: Style {
+                                            flex_direction: FlexDirection::Column,
+                                            ..default()
+                                        },
+                                        ..default()
+                                    })
+                                    .with_children(|parent| {
+                                        for action in column {
+                                            spawn_sized_button(
+                                                parent,
+                                                font.clone(),
+                                                BINDING_FONT_SIZE,
+                                                &binding_label(*action, &key_bindings, &rebinding),
+                                                ButtonAction::Rebind(*action),
+                                            );
+                                        }
+                                    });
+                            }
+                        });
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                justify_content: JustifyContent::Center,
+                                ..default()
+                            },
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            spawn_button(
+                                parent,
+                                font.clone(),
+                                "Reset",
+                                ButtonAction::ResetControls,
+                            );
+                            spawn_button(parent, font.clone(), "Back", ButtonAction::BackToMenu);
+                        });
+                });
+        });
+    commands.insert_resource(rebinding);
+}
+
+/// Binds the next key pressed to the action being rebound, or goes back to the menu.
+pub fn rebind_key(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    actions: Res<Input<Action>>,
+    mut rebinding: ResMut<Rebinding>,
+    mut key_bindings: ResMut<KeyBindings>,
+    mut state: ResMut<NextState<AppState>>,
+) {
+    let Some(action) = rebinding.0 else {
+        if actions.just_pressed(Action::Back) {
+            state.set(AppState::MainMenu);
+        }
+        return;
+    };
+    // Any key can be bound, including the one going back
+    if let Some(key) = keyboard_input.get_just_pressed().next() {
+        key_bindings.bind(action, *key);
+        rebinding.0 = None;
+        commands.remove_resource::<KeyboardCaptured>();
+    }
+}
+
+pub fn update_binding_labels(
+    key_bindings: Res<KeyBindings>,
+    rebinding: Res<Rebinding>,
+    button_query: Query<(&ButtonAction, &Children)>,
+    mut text_query: Query<&mut Text>,
+) {
+    if !key_bindings.is_changed() && !rebinding.is_changed() {
+        return;
+    }
+    for (action, children) in button_query.iter() {
+        let ButtonAction::Rebind(action) = action else {
+            continue;
+        };
+        let mut texts = text_query.iter_many_mut(children);
+        while let Some(mut text) = texts.fetch_next() {
+            text.sections[0].value = binding_label(*action, &key_bindings, &rebinding);
+        }
+    }
+}
+
+pub fn despawn_controls(mut commands: Commands, query: Query<Entity, With<ControlsScreen>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands.remove_resource::<Rebinding>();
+    commands.remove_resource::<KeyboardCaptured>();
+}
@@ -0,0 +1,109 @@
+use bevy::prelude::*;
+
+use crate::actions::MenuAction;
+
+use super::{ButtonAction, ButtonColors};
+
+/// The button activated by [`MenuAction::Activate`], highlighted like a hovered one.
+#[derive(Component)]
+pub struct Focused;
+
+/// Sent when a button is clicked, or activated while focused.
+pub struct ButtonPressed(pub ButtonAction);
+
+/// Every button on screen, in the order they are laid out in.
+fn buttons_in_order(
+    root_query: &Query<Entity, (With<Node>, Without<Parent>)>,
+    children_query: &Query<&Children>,
+    button_query: &Query<(), With<ButtonAction>>,
+) -> Vec<Entity> {
+    let mut buttons = vec![];
+    let mut stack: Vec<Entity> = root_query.iter().collect();
+    stack.reverse();
+    while let Some(entity) = stack.pop() {
+        if button_query.contains(entity) {
+            buttons.push(entity);
+        }
+        if let Ok(children) = children_query.get(entity) {
+            stack.extend(children.iter().rev());
+        }
+    }
+    buttons
+}
+
+/// Moves the focus between buttons, focusing the first one when none is.
+pub fn move_focus(
+    mut commands: Commands,
+    menu_actions: Res<Input<MenuAction>>,
+    root_query: Query<Entity, (With<Node>, Without<Parent>)>,
+    children_query: Query<&Children>,
+    button_query: Query<(), With<ButtonAction>>,
+    focused_query: Query<Entity, With<Focused>>,
+    hovered_query: Query<(Entity, &Interaction), Changed<Interaction>>,
+) {
+    let focused = focused_query.get_single().ok();
+    // The mouse moves the focus too, so hovering and focusing look the same
+    let hovered = hovered_query
+        .iter()
+        .find(|(_, interaction)| **interaction == Interaction::Hovered)
+        .map(|(entity, _)| entity);
+    let target = if hovered.is_some() {
+        hovered
+    } else {
+        let buttons = buttons_in_order(&root_query, &children_query, &button_query);
+        let index = focused.and_then(|focused| buttons.iter().position(|b| *b == focused));
+        match index {
+            None => buttons.first().copied(),
+            Some(index) if menu_actions.just_pressed(MenuAction::Next) => {
+                Some(buttons[(index + 1) % buttons.len()])
+            }
+            Some(index) if menu_actions.just_pressed(MenuAction::Previous) => {
+                Some(buttons[(index + buttons.len() - 1) % buttons.len()])
+            }
+            Some(_) => focused,
+        }
+    };
+    if target == focused {
+        return;
+    }
+    for entity in focused_query.iter() {
+        commands.entity(entity).remove::<Focused>();
+    }
+    if let Some(target) = target {
+        commands.entity(target).insert(Focused);
+    }
+}
+
+pub fn press_button(
+    menu_actions: Res<Input<MenuAction>>,
+    interaction_query: Query<(&Interaction, &ButtonAction), Changed<Interaction>>,
+    focused_query: Query<&ButtonAction, With<Focused>>,
+    mut pressed: EventWriter<ButtonPressed>,
+) {
+    for (interaction, action) in interaction_query.iter() {
+        if *interaction == Interaction::Clicked {
+            pressed.send(ButtonPressed(*action));
+        }
+    }
+    if menu_actions.just_pressed(MenuAction::Activate) {
+        if let Ok(action) = focused_query.get_single() {
+            pressed.send(ButtonPressed(*action));
+        }
+    }
+}
+
+pub fn highlight_focused_button(
+    button_colors: Res<ButtonColors>,
+    mut button_query: Query<(&mut BackgroundColor, Option<&Focused>), With<ButtonAction>>,
+) {
+    for (mut color, focused) in button_query.iter_mut() {
+        let target = if focused.is_some() {
+            button_colors.hovered
+        } else {
+            button_colors.default
+        };
+        if color.0 != target {
+            color.0 = target;
+        }
+    }
+}
@@ -1,9 +1,10 @@
 use bevy::prelude::*;
 
 use crate::{
-    effects::{Effect, EffectQueue},
-    events::{GameTimer, MAX_TIME_TO_REACH_WALL},
-    Score,
+    difficulty::Difficulty,
+    effects::{ActiveEffects, Effect},
+    events::{GameTimer, TimeAdded, WallReached},
+    Score, Wall,
 };
 
 const TIME_SECTION: usize = 1;
@@ -32,33 +33,103 @@ pub struct TimerText;
 #[derive(Component)]
 pub struct ScoreText;
 
+const TIME_BONUS_POPUP_SECS: f32 = 1.0;
+/// Where the popup starts, right below the timer.
+const TIME_BONUS_POPUP_TOP: f32 = 40.0;
+/// How far the popup rises while it fades out.
+const TIME_BONUS_POPUP_RISE: f32 = 24.0;
+
+/// The time added to the bank timer, floating up from the timer.
+#[derive(Component)]
+pub struct TimeBonusPopup(Timer);
+
 pub fn update_timer(timer: Res<GameTimer>, mut query: Query<&mut Text, With<TimerText>>) {
     let Ok(mut text) = query.get_single_mut() else {
         return;
     };
-    let elapsed = timer.0.elapsed().as_secs_f32();
-    let time_left_secs = MAX_TIME_TO_REACH_WALL - elapsed;
+    let time_left_secs = timer.time_left_secs();
     if time_left_secs < 6.0 {
         text.sections[TIME_SECTION].style.font_size =
             36.0 + f32::min(20., 24. - 4. * time_left_secs.floor());
     } else {
         text.sections[TIME_SECTION].style.font_size = 36.0;
     }
-    text.sections[TIME_SECTION].value = format!("{:.2}", f32::max(0.0, time_left_secs));
+    text.sections[TIME_SECTION].value = format!("{:.2}", time_left_secs);
+}
+
+pub fn spawn_time_bonus_popup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut time_added: EventReader<TimeAdded>,
+    hud_query: Query<Entity, With<Hud>>,
+) {
+    let Ok(hud) = hud_query.get_single() else {
+        return;
+    };
+    for TimeAdded(secs) in time_added.iter() {
+        let popup = commands
+            .spawn((
+                TextBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        position: UiRect {
+                            left: Val::Px(16.),
+                            top: Val::Px(TIME_BONUS_POPUP_TOP),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    text: Text::from_section(
+                        // Rounded to a tenth, curves can give any amount
+                        format!("+{} s", (secs * 10.).round() / 10.),
+                        TextStyle {
+                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                            font_size: 28.0,
+                            color: Color::LIME_GREEN,
+                        },
+                    ),
+                    ..default()
+                },
+                TimeBonusPopup(Timer::from_seconds(TIME_BONUS_POPUP_SECS, TimerMode::Once)),
+            ))
+            .id();
+        commands.entity(hud).add_child(popup);
+    }
 }
 
-pub fn update_effect(effect: Res<EffectQueue>, mut query: Query<&mut Text, With<DirectionText>>) {
+pub fn animate_time_bonus_popups(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut TimeBonusPopup, &mut Style, &mut Text)>,
+) {
+    for (entity, mut popup, mut style, mut text) in query.iter_mut() {
+        if popup.0.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        let progress = popup.0.percent();
+        style.position.top = Val::Px(TIME_BONUS_POPUP_TOP - TIME_BONUS_POPUP_RISE * progress);
+        text.sections[0].style.color.set_a(1. - progress);
+    }
+}
+
+pub fn update_effect(
+    active_effects: Res<ActiveEffects>,
+    mut query: Query<&mut Text, With<DirectionText>>,
+) {
     let Ok(mut text) = query.get_single_mut() else {
         return;
     };
-    if effect.is_changed() {
-        if let Some(effect) = effect.0.last() {
-            text.sections[EFFECT_SECTION].value = effect.to_string();
-            if effect == &Effect::Darkness {
-                text.sections[EFFECT_SECTION].style.color = Color::WHITE;
-            } else {
-                text.sections[EFFECT_SECTION].style.color = Color::BLACK;
-            }
+    if active_effects.is_changed() {
+        text.sections[EFFECT_SECTION].value = active_effects
+            .iter()
+            .map(Effect::to_string)
+            .collect::<Vec<_>>()
+            .join(" + ");
+        if active_effects.contains(&Effect::Darkness) {
+            text.sections[EFFECT_SECTION].style.color = Color::WHITE;
+        } else {
+            text.sections[EFFECT_SECTION].style.color = Color::BLACK;
         }
     }
 }
@@ -72,20 +143,28 @@ pub fn update_score(score: Res<Score>, mut query: Query<&mut Text, With<ScoreTex
     }
 }
 
-pub fn update_direction(score: Res<Score>, mut query: Query<&mut Text, With<DirectionText>>) {
-    if score.is_changed() {
-        let Ok(mut text) = query.get_single_mut() else {
-            return;
-        };
-        match text.sections[DIRECTION_SECTION].value.as_str() {
-            GO_LEFT_TEXT => text.sections[DIRECTION_SECTION].value = GO_RIGHT_TEXT.to_string(),
-            GO_RIGHT_TEXT => text.sections[DIRECTION_SECTION].value = GO_LEFT_TEXT.to_string(),
-            _ => {}
-        }
+pub fn update_direction(
+    mut wall_reached: EventReader<WallReached>,
+    mut query: Query<&mut Text, With<DirectionText>>,
+) {
+    let Some(WallReached(wall)) = wall_reached.iter().last() else {
+        return;
+    };
+    let Ok(mut text) = query.get_single_mut() else {
+        return;
+    };
+    text.sections[DIRECTION_SECTION].value = match wall {
+        Wall::Left => GO_RIGHT_TEXT,
+        Wall::Right => GO_LEFT_TEXT,
     }
+    .to_string();
 }
 
-pub fn spawn_hud(mut commands: Commands, asset_server: Res<AssetServer>) {
+pub fn spawn_hud(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    difficulty: Res<Difficulty>,
+) {
     let font = asset_server.load("fonts/FiraSans-Bold.ttf");
     commands
         .spawn((
@@ -112,7 +191,7 @@ pub fn spawn_hud(mut commands: Commands, asset_server: Res<AssetServer>) {
                         sections: vec![
                             TextSection::new("Time: ", hud_text_style(font.clone())),
                             TextSection::new(
-                                format!("{:.2}", MAX_TIME_TO_REACH_WALL),
+                                format!("{:.2}", difficulty.curves.time_to_reach_wall.at(0)),
                                 hud_text_style(font.clone()),
                             ),
                         ],
@@ -131,7 +210,7 @@ pub fn spawn_hud(mut commands: Commands, asset_server: Res<AssetServer>) {
                     },
                     text: Text {
                         sections: vec![
-                            TextSection::new(">>>", hud_text_style(font.clone())),
+                            TextSection::new(GO_LEFT_TEXT, hud_text_style(font.clone())),
                             TextSection::new("\n", hud_text_style(font.clone())),
                             TextSection::new(
                                 "",
@@ -0,0 +1,139 @@
+use bevy::prelude::*;
+
+use crate::{effects::Effect, leaderboard::Leaderboard};
+
+use super::{spawn_button, ButtonAction};
+
+#[derive(Component)]
+pub struct LeaderboardScreen;
+
+const TITLE_TEXT: &str = "Leaderboard";
+const EMPTY_TEXT: &str = "No runs yet, go set one!";
+
+fn effects_label(effects: &[Effect]) -> String {
+    if effects.is_empty() {
+        return "No effects".to_string();
+    }
+    effects
+        .iter()
+        .map(|effect| effect.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+pub fn spawn_leaderboard(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    leaderboard: Res<Leaderboard>,
+) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: BackgroundColor(Color::DARK_GRAY),
+                ..default()
+            },
+            LeaderboardScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn((TextBundle {
+                        style: Style {
+                            margin: UiRect::vertical(Val::Px(12.)),
+                            align_self: AlignSelf::Center,
+                            ..default()
+                        },
+                        text: Text::from_section(
+                            TITLE_TEXT,
+                            TextStyle {
+                                font: font.clone(),
+                                font_size: 48.0,
+                                color: Color::CRIMSON,
+                            },
+                        ),
+                        ..default()
+                    },));
+                    if leaderboard.is_empty() {
+                        parent.spawn((TextBundle {
+                            style: Style {
+                                align_self: AlignSelf::Center,
+                                ..default()
+                            },
+                            text: Text::from_section(
+                                EMPTY_TEXT,
+                                TextStyle {
+                                    font: font.clone(),
+                                    font_size: 20.0,
+                                    color: Color::WHITE,
+                                },
+                            ),
+                            ..default()
+                        },));
+                    }
+                    for (rank, entry) in leaderboard.iter().enumerate() {
+                        parent.spawn((TextBundle {
+                            style: Style {
+                                margin: UiRect::bottom(Val::Px(2.)),
+                                ..default()
+                            },
+                            text: Text::from_sections([
+                                TextSection::new(
+                                    format!("{}. {} ", rank + 1, entry.name),
+                                    TextStyle {
+                                        font: font.clone(),
+                                        font_size: 16.0,
+                                        color: Color::WHITE,
+                                    },
+                                ),
+                                TextSection::new(
+                                    format!("{} ", entry.score),
+                                    TextStyle {
+                                        font: font.clone(),
+                                        font_size: 16.0,
+                                        color: Color::RED,
+                                    },
+                                ),
+                                TextSection::new(
+                                    format!(
+                                        "{} | {}, {:?}, {} | {:?} | {} | {}",
+                                        entry.date_string(),
+                                        entry.difficulty,
+                                        entry.mode,
+                                        entry.timer_mode,
+                                        entry.reason,
+                                        entry.seed,
+                                        effects_label(&entry.effects),
+                                    ),
+                                    TextStyle {
+                                        font: font.clone(),
+                                        font_size: 12.0,
+                                        color: Color::ORANGE_RED,
+                                    },
+                                ),
+                            ]),
+                            ..default()
+                        },));
+                    }
+                    spawn_button(parent, font.clone(), "Back", ButtonAction::BackToMenu);
+                });
+        });
+}
+
+pub fn despawn_leaderboard(mut commands: Commands, query: Query<Entity, With<LeaderboardScreen>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -1,16 +1,28 @@
 use bevy::prelude::*;
 
-use super::ButtonColors;
+use crate::{settings::KeyBindings, GameMode};
+
+use super::{spawn_button, ButtonAction};
 
 #[derive(Component)]
 pub struct MainMenu;
 
 const TITLE_TEXT: &str = "SideSwap";
 const DESCRIPTION_TEXT: &str = "Side effects, literally.";
-const TUTORIAL_TEXT: &str =
-    "Use [A, D] to move.\n Press SPACE to jump.\n Get to the wall highlighted in green!";
 
-pub fn spawn_main_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+fn tutorial_text(key_bindings: &KeyBindings) -> String {
+    format!(
+        "Use [{:?}, {:?}] to move.\n Press {:?} to jump.\n Get to the wall highlighted in green!",
+        key_bindings.move_left, key_bindings.move_right, key_bindings.jump
+    )
+}
+
+pub fn spawn_main_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    game_mode: Res<GameMode>,
+    key_bindings: Res<KeyBindings>,
+) {
     let font = asset_server.load("fonts/FiraSans-Bold.ttf");
     commands
         .spawn((
@@ -80,7 +92,7 @@ pub fn spawn_main_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
                         },
                         text: Text {
                             sections: vec![TextSection::new(
-                                TUTORIAL_TEXT,
+                                tutorial_text(&key_bindings),
                                 TextStyle {
                                     font: font.clone(),
                                     font_size: 24.0,
@@ -92,28 +104,40 @@ pub fn spawn_main_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
                         },
                         ..default()
                     },));
+                    spawn_button(
+                        parent,
+                        font.clone(),
+                        "Let's GOOOO!",
+                        ButtonAction::Play(*game_mode),
+                    );
+                    // The other screens side by side, to fit under the tutorial
                     parent
-                        .spawn(ButtonBundle {
+                        .spawn(NodeBundle {
                             style: Style {
                                 justify_content: JustifyContent::Center,
-                                align_items: AlignItems::Center,
-                                align_self: AlignSelf::Center,
-                                max_size: Size::new(Val::Px(250.), Val::Auto),
-                                margin: UiRect::all(Val::Px(8.)),
                                 ..default()
                             },
-                            background_color: BackgroundColor(ButtonColors::default().default),
                             ..default()
                         })
                         .with_children(|parent| {
-                            parent.spawn(TextBundle::from_section(
-                                "Let's GOOOO!",
-                                TextStyle {
-                                    font: font.clone(),
-                                    font_size: 32.0,
-                                    color: Color::WHITE,
-                                },
-                            ));
+                            spawn_button(
+                                parent,
+                                font.clone(),
+                                "Leaderboard",
+                                ButtonAction::ShowLeaderboard,
+                            );
+                            spawn_button(
+                                parent,
+                                font.clone(),
+                                "Controls",
+                                ButtonAction::ShowControls,
+                            );
+                            spawn_button(
+                                parent,
+                                font.clone(),
+                                "Settings",
+                                ButtonAction::ShowSettings,
+                            );
                         });
                 });
         });
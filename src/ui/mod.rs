@@ -1,31 +1,93 @@
-use bevy::prelude::*;
+use bevy::{ecs::system::EntityCommands, prelude::*};
 
-use crate::AppState;
+use crate::{
+    actions::{Action, KeyboardCaptured},
+    difficulty::{choose_difficulty, DifficultyLevel},
+    leaderboard::SubmitEntry,
+    pause::PauseState,
+    recording::WatchReplay,
+    rng::{NextRunSeed, RunSeed},
+    settings::{KeyBindings, Volume, WallTimerMode},
+    shake::ScreenShake,
+    AppState, GameMode,
+};
 
+use self::controls::{
+    despawn_controls, rebind_key, spawn_controls, update_binding_labels, Rebinding,
+};
+use self::focus::{highlight_focused_button, move_focus, press_button, ButtonPressed};
 use self::hud::{
-    despawn_hud, spawn_hud, update_direction, update_effect, update_score, update_timer,
+    animate_time_bonus_popups, despawn_hud, spawn_hud, spawn_time_bonus_popup, update_direction,
+    update_effect, update_score, update_timer,
 };
+use self::leaderboard::{despawn_leaderboard, spawn_leaderboard};
 use self::menu::{despawn_main_menu, spawn_main_menu};
-use self::replay::{despawn_replay_screen, spawn_replay_screen};
+use self::pause::{despawn_pause_menu, spawn_pause_menu};
+use self::replay::{despawn_replay_screen, spawn_replay_screen, type_name, update_name_entry};
+use self::settings::{despawn_settings, spawn_settings, update_settings_labels};
 
+mod controls;
+mod focus;
 mod hud;
+mod leaderboard;
 mod menu;
+mod pause;
 mod replay;
+mod settings;
 
 pub struct UIPlugin;
 
 impl Plugin for UIPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<ButtonColors>()
+            .add_event::<ButtonPressed>()
             .add_system(spawn_main_menu.in_schedule(OnEnter(AppState::MainMenu)))
             .add_system(spawn_replay_screen.in_schedule(OnEnter(AppState::YouDied)))
-            .add_system(spawn_hud.in_schedule(OnEnter(AppState::InGame)))
-            .add_system(click_play_button.in_set(OnUpdate(AppState::MainMenu)))
-            .add_system(click_play_button.in_set(OnUpdate(AppState::YouDied)))
+            .add_system(
+                spawn_hud
+                    .after(choose_difficulty)
+                    .in_schedule(OnEnter(AppState::InGame)),
+            )
+            .add_systems(
+                (
+                    move_focus,
+                    press_button,
+                    handle_button_action,
+                    highlight_focused_button,
+                )
+                    .chain(),
+            )
+            .add_systems((type_name, update_name_entry).in_set(OnUpdate(AppState::YouDied)))
+            .add_system(spawn_leaderboard.in_schedule(OnEnter(AppState::Leaderboard)))
+            .add_system(back_to_menu.in_set(OnUpdate(AppState::Leaderboard)))
+            .add_system(spawn_settings.in_schedule(OnEnter(AppState::Settings)))
             .add_systems(
-                (update_score, update_effect, update_timer, update_direction)
+                (back_to_menu, update_settings_labels).in_set(OnUpdate(AppState::Settings)),
+            )
+            .add_system(despawn_settings.in_schedule(OnExit(AppState::Settings)))
+            .add_system(spawn_controls.in_schedule(OnEnter(AppState::Controls)))
+            .add_systems(
+                (
+                    rebind_key.before(handle_button_action),
+                    update_binding_labels,
+                )
+                    .in_set(OnUpdate(AppState::Controls)),
+            )
+            .add_system(despawn_controls.in_schedule(OnExit(AppState::Controls)))
+            .add_system(despawn_leaderboard.in_schedule(OnExit(AppState::Leaderboard)))
+            .add_systems(
+                (
+                    update_score,
+                    update_effect,
+                    update_timer,
+                    update_direction,
+                    spawn_time_bonus_popup,
+                    animate_time_bonus_popups,
+                )
                     .in_set(OnUpdate(AppState::InGame)),
             )
+            .add_system(spawn_pause_menu.in_schedule(OnEnter(PauseState::Paused)))
+            .add_system(despawn_pause_menu.in_schedule(OnExit(PauseState::Paused)))
             .add_system(despawn_main_menu.in_schedule(OnExit(AppState::MainMenu)))
             .add_system(despawn_hud.in_schedule(OnExit(AppState::InGame)))
             .add_system(despawn_replay_screen.in_schedule(OnExit(AppState::YouDied)));
@@ -50,25 +112,166 @@ impl Default for ButtonColors {
     }
 }
 
-pub fn click_play_button(
-    button_colors: Res<ButtonColors>,
+/// What a button does when clicked.
+#[derive(Component, Clone, Copy)]
+pub enum ButtonAction {
+    Play(GameMode),
+    /// Play again from the given seed.
+    PlaySeed(GameMode, RunSeed),
+    /// Watch the recording of the last run.
+    WatchReplay,
+    CycleGameMode,
+    CycleScreenShake,
+    CycleVolume,
+    CycleWallTimerMode,
+    CycleDifficulty,
+    ShowLeaderboard,
+    ShowControls,
+    ShowSettings,
+    /// Wait for a key to bind to the action.
+    Rebind(Action),
+    /// Go back to the default key bindings.
+    ResetControls,
+    /// Add the run that just ended to the leaderboard.
+    SubmitEntry,
+    BackToMenu,
+    Resume,
+    /// Start a new run in the same mode.
+    Restart,
+    QuitToMenu,
+}
+
+pub fn spawn_button<'w, 's, 'a>(
+    parent: &'a mut ChildBuilder<'w, 's, '_>,
+    font: Handle<Font>,
+    label: &str,
+    action: ButtonAction,
+) -> EntityCommands<'w, 's, 'a> {
+    spawn_sized_button(parent, font, 32., label, action)
+}
+
+/// A button with a label smaller or larger than the usual one.
+pub fn spawn_sized_button<'w, 's, 'a>(
+    parent: &'a mut ChildBuilder<'w, 's, '_>,
+    font: Handle<Font>,
+    font_size: f32,
+    label: &str,
+    action: ButtonAction,
+) -> EntityCommands<'w, 's, 'a> {
+    let mut button = parent.spawn((
+        ButtonBundle {
+            style: Style {
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                align_self: AlignSelf::Center,
+                max_size: Size::new(Val::Px(275.), Val::Auto),
+                margin: UiRect::all(Val::Px(8.)),
+                ..default()
+            },
+            background_color: BackgroundColor(ButtonColors::default().default),
+            ..default()
+        },
+        action,
+    ));
+    button.with_children(|parent| {
+        parent.spawn(TextBundle::from_section(
+            label,
+            TextStyle {
+                font,
+                font_size,
+                color: Color::WHITE,
+            },
+        ));
+    });
+    button
+}
+
+pub fn handle_button_action(
+    mut commands: Commands,
+    mut pressed: EventReader<ButtonPressed>,
+    mut game_mode: ResMut<GameMode>,
+    mut screen_shake: ResMut<ScreenShake>,
+    mut volume: ResMut<Volume>,
+    mut wall_timer_mode: ResMut<WallTimerMode>,
+    mut difficulty_level: ResMut<DifficultyLevel>,
+    mut key_bindings: ResMut<KeyBindings>,
+    mut rebinding: Option<ResMut<Rebinding>>,
+    mut next_seed: ResMut<NextRunSeed>,
+    mut watch_replay: EventWriter<WatchReplay>,
+    mut submit_entry: EventWriter<SubmitEntry>,
     mut state: ResMut<NextState<AppState>>,
-    mut interaction_query: Query<
-        (&Interaction, &mut BackgroundColor),
-        (Changed<Interaction>, With<Button>),
-    >,
+    mut pause_state: ResMut<NextState<PauseState>>,
 ) {
-    for (interaction, mut color) in &mut interaction_query {
-        match *interaction {
-            Interaction::Clicked => {
+    for ButtonPressed(action) in pressed.iter() {
+        match *action {
+            ButtonAction::Play(mode) => {
+                *game_mode = mode;
+                state.set(AppState::InGame);
+            }
+            ButtonAction::PlaySeed(mode, seed) => {
+                *game_mode = mode;
+                next_seed.0 = Some(seed);
                 state.set(AppState::InGame);
             }
-            Interaction::Hovered => {
-                *color = button_colors.hovered.into();
+            ButtonAction::WatchReplay => {
+                watch_replay.send(WatchReplay);
             }
-            Interaction::None => {
-                *color = button_colors.default.into();
+            ButtonAction::CycleGameMode => {
+                *game_mode = game_mode.next();
+            }
+            ButtonAction::CycleScreenShake => {
+                *screen_shake = screen_shake.next();
+            }
+            ButtonAction::CycleVolume => {
+                *volume = volume.next();
+            }
+            ButtonAction::CycleWallTimerMode => {
+                *wall_timer_mode = wall_timer_mode.next();
+            }
+            ButtonAction::CycleDifficulty => {
+                *difficulty_level = difficulty_level.next();
+            }
+            ButtonAction::ShowLeaderboard => {
+                state.set(AppState::Leaderboard);
+            }
+            ButtonAction::ShowControls => {
+                state.set(AppState::Controls);
+            }
+            ButtonAction::ShowSettings => {
+                state.set(AppState::Settings);
+            }
+            ButtonAction::Rebind(action) => {
+                if let Some(rebinding) = rebinding.as_mut() {
+                    rebinding.0 = Some(action);
+                    commands.insert_resource(KeyboardCaptured);
+                }
+            }
+            ButtonAction::ResetControls => {
+                *key_bindings = KeyBindings::default();
+            }
+            ButtonAction::SubmitEntry => {
+                submit_entry.send(SubmitEntry);
+            }
+            ButtonAction::BackToMenu => {
+                state.set(AppState::MainMenu);
+            }
+            ButtonAction::Resume => {
+                pause_state.set(PauseState::Running);
+            }
+            ButtonAction::Restart => {
+                pause_state.set(PauseState::Running);
+                state.set(AppState::InGame);
+            }
+            ButtonAction::QuitToMenu => {
+                pause_state.set(PauseState::Running);
+                state.set(AppState::MainMenu);
             }
         }
     }
 }
+
+pub fn back_to_menu(actions: Res<Input<Action>>, mut state: ResMut<NextState<AppState>>) {
+    if actions.just_pressed(Action::Back) {
+        state.set(AppState::MainMenu);
+    }
+}
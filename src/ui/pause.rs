@@ -0,0 +1,71 @@
+use bevy::prelude::*;
+
+use super::{focus::Focused, spawn_button, ButtonAction};
+
+#[derive(Component)]
+pub struct PauseMenu;
+
+const PAUSED_TEXT: &str = "PAUSED";
+
+pub fn spawn_pause_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: BackgroundColor(Color::rgba(0., 0., 0., 0.6)),
+                // Above the HUD
+                z_index: ZIndex::Global(1),
+                ..default()
+            },
+            PauseMenu,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        justify_content: JustifyContent::Center,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn((TextBundle {
+                        style: Style {
+                            margin: UiRect::bottom(Val::Px(16.)),
+                            align_self: AlignSelf::Center,
+                            ..default()
+                        },
+                        text: Text::from_section(
+                            PAUSED_TEXT,
+                            TextStyle {
+                                font: font.clone(),
+                                font_size: 80.0,
+                                color: Color::CRIMSON,
+                            },
+                        ),
+                        ..default()
+                    },));
+                    spawn_button(parent, font.clone(), "Resume", ButtonAction::Resume)
+                        .insert(Focused);
+                    spawn_button(parent, font.clone(), "Restart", ButtonAction::Restart);
+                    spawn_button(
+                        parent,
+                        font.clone(),
+                        "Quit to Menu",
+                        ButtonAction::QuitToMenu,
+                    );
+                });
+        });
+}
+
+pub fn despawn_pause_menu(mut commands: Commands, query: Query<Entity, With<PauseMenu>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
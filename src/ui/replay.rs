@@ -1,14 +1,26 @@
 use bevy::prelude::*;
 
-use crate::{events::Reason, HighScore, Score};
+use crate::{
+    actions::KeyboardCaptured,
+    events::Reason,
+    leaderboard::{EntrySubmitted, Leaderboard, PendingEntry, SubmitEntry, MAX_NAME_LENGTH},
+    rng::RunSeed,
+    GameMode, HighScore, Score,
+};
 
-use super::ButtonColors;
+use super::{focus::Focused, spawn_button, ButtonAction};
 
 #[derive(Component)]
 pub struct ReplayScreen;
 
+/// Text showing the name typed for the leaderboard.
+#[derive(Component)]
+pub struct NameEntry;
+
 const YOU_DIED_TEXT: &str = "YOU DIED!";
 const OUT_OF_TIME_TEXT: &str = "OUT OF TIME!";
+const NEW_RECORD_TEXT: &str = "New record! Name: ";
+const NAME_CURSOR: &str = "_";
 
 pub fn spawn_replay_screen(
     mut commands: Commands,
@@ -16,8 +28,15 @@ pub fn spawn_replay_screen(
     highscore: Res<HighScore>,
     asset_server: Res<AssetServer>,
     loss_reason: Res<Reason>,
+    game_mode: Res<GameMode>,
+    seed: Res<RunSeed>,
+    pending_entry: Option<Res<PendingEntry>>,
 ) {
     let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    // Typing the name takes the keyboard until it is submitted
+    if pending_entry.is_some() {
+        commands.insert_resource(KeyboardCaptured);
+    }
     let title = match *loss_reason {
         Reason::OutOfTime => OUT_OF_TIME_TEXT,
         Reason::Died => YOU_DIED_TEXT,
@@ -47,7 +66,7 @@ pub fn spawn_replay_screen(
                 .with_children(|parent| {
                     parent.spawn((TextBundle {
                         style: Style {
-                            margin: UiRect::vertical(Val::Px(18.)),
+                            margin: UiRect::vertical(Val::Px(8.)),
                             ..default()
                         },
                         text: Text {
@@ -55,7 +74,7 @@ pub fn spawn_replay_screen(
                                 title,
                                 TextStyle {
                                     font: font.clone(),
-                                    font_size: 110.,
+                                    font_size: 72.,
                                     color: Color::CRIMSON,
                                 },
                             )],
@@ -66,7 +85,7 @@ pub fn spawn_replay_screen(
                     },));
                     parent.spawn((TextBundle {
                         style: Style {
-                            margin: UiRect::bottom(Val::Px(8.)),
+                            margin: UiRect::bottom(Val::Px(4.)),
                             justify_content: JustifyContent::Center,
                             align_items: AlignItems::Center,
                             align_self: AlignSelf::Center,
@@ -98,7 +117,7 @@ pub fn spawn_replay_screen(
                     },));
                     parent.spawn((TextBundle {
                         style: Style {
-                            margin: UiRect::bottom(Val::Px(8.)),
+                            margin: UiRect::bottom(Val::Px(4.)),
                             justify_content: JustifyContent::Center,
                             align_items: AlignItems::Center,
                             align_self: AlignSelf::Center,
@@ -128,35 +147,170 @@ pub fn spawn_replay_screen(
                         },
                         ..default()
                     },));
+                    parent.spawn((TextBundle {
+                        style: Style {
+                            margin: UiRect::bottom(Val::Px(4.)),
+                            align_self: AlignSelf::Center,
+                            ..default()
+                        },
+                        text: Text {
+                            sections: vec![
+                                TextSection::new(
+                                    "Seed: ",
+                                    TextStyle {
+                                        font: font.clone(),
+                                        font_size: 20.0,
+                                        color: Color::ORANGE_RED,
+                                    },
+                                ),
+                                TextSection::new(
+                                    seed.to_string(),
+                                    TextStyle {
+                                        font: font.clone(),
+                                        font_size: 20.0,
+                                        color: Color::WHITE,
+                                    },
+                                ),
+                            ],
+                            alignment: TextAlignment::Center,
+                            ..default()
+                        },
+                        ..default()
+                    },));
+                    if let Some(pending_entry) = pending_entry {
+                        parent.spawn((
+                            TextBundle {
+                                style: Style {
+                                    margin: UiRect::bottom(Val::Px(4.)),
+                                    align_self: AlignSelf::Center,
+                                    ..default()
+                                },
+                                text: Text {
+                                    sections: vec![
+                                        TextSection::new(
+                                            NEW_RECORD_TEXT,
+                                            TextStyle {
+                                                font: font.clone(),
+                                                font_size: 30.0,
+                                                color: Color::ORANGE_RED,
+                                            },
+                                        ),
+                                        TextSection::new(
+                                            pending_entry.name.clone() + NAME_CURSOR,
+                                            TextStyle {
+                                                font: font.clone(),
+                                                font_size: 30.0,
+                                                color: Color::WHITE,
+                                            },
+                                        ),
+                                    ],
+                                    alignment: TextAlignment::Center,
+                                    ..default()
+                                },
+                                ..default()
+                            },
+                            NameEntry,
+                        ));
+                        spawn_button(
+                            parent,
+                            font.clone(),
+                            "Save Score",
+                            ButtonAction::SubmitEntry,
+                        );
+                    }
+                    // One row, so the buttons stay on screen under the name entry
                     parent
-                        .spawn(ButtonBundle {
+                        .spawn(NodeBundle {
                             style: Style {
                                 justify_content: JustifyContent::Center,
-                                align_items: AlignItems::Center,
-                                align_self: AlignSelf::Center,
-                                max_size: Size::new(Val::Px(275.), Val::Auto),
-                                margin: UiRect::all(Val::Px(8.)),
                                 ..default()
                             },
-                            background_color: BackgroundColor(ButtonColors::default().default),
                             ..default()
                         })
                         .with_children(|parent| {
-                            parent.spawn(TextBundle::from_section(
+                            // Focused first, so Space restarts right away
+                            spawn_button(
+                                parent,
+                                font.clone(),
                                 "Again >:)",
-                                TextStyle {
-                                    font: font.clone(),
-                                    font_size: 32.0,
-                                    color: Color::WHITE,
-                                },
-                            ));
+                                ButtonAction::Play(*game_mode),
+                            )
+                            .insert(Focused);
+                            spawn_button(
+                                parent,
+                                font.clone(),
+                                "Same Seed",
+                                ButtonAction::PlaySeed(*game_mode, *seed),
+                            );
+                            spawn_button(
+                                parent,
+                                font.clone(),
+                                "Watch Replay",
+                                ButtonAction::WatchReplay,
+                            );
                         });
                 });
         });
 }
 
+pub fn type_name(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut characters: EventReader<ReceivedCharacter>,
+    pending_entry: Option<ResMut<PendingEntry>>,
+    mut submit_entry: EventWriter<SubmitEntry>,
+) {
+    let Some(mut pending_entry) = pending_entry else {
+        characters.clear();
+        return;
+    };
+    for character in characters.iter() {
+        if !character.char.is_control() && pending_entry.name.chars().count() < MAX_NAME_LENGTH {
+            pending_entry.name.push(character.char);
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::Back) {
+        pending_entry.name.pop();
+    }
+    if keyboard_input.just_pressed(KeyCode::Return) {
+        submit_entry.send(SubmitEntry);
+    }
+}
+
+pub fn update_name_entry(
+    mut commands: Commands,
+    pending_entry: Option<Res<PendingEntry>>,
+    leaderboard: Res<Leaderboard>,
+    mut submitted: EventReader<EntrySubmitted>,
+    mut text_query: Query<&mut Text, With<NameEntry>>,
+    button_query: Query<(Entity, &ButtonAction)>,
+) {
+    if let Some(EntrySubmitted(rank)) = submitted.iter().last() {
+        commands.remove_resource::<KeyboardCaptured>();
+        for mut text in text_query.iter_mut() {
+            text.sections[0].value = format!("Ranked #{}: ", rank + 1);
+            text.sections[1].value = leaderboard[*rank].name.clone();
+        }
+        for (entity, action) in button_query.iter() {
+            if matches!(action, ButtonAction::SubmitEntry) {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+        return;
+    }
+    let Some(pending_entry) = pending_entry else {
+        return;
+    };
+    if !pending_entry.is_changed() {
+        return;
+    }
+    for mut text in text_query.iter_mut() {
+        text.sections[1].value = pending_entry.name.clone() + NAME_CURSOR;
+    }
+}
+
 pub fn despawn_replay_screen(mut commands: Commands, query: Query<Entity, With<ReplayScreen>>) {
     for entity in query.iter() {
         commands.entity(entity).despawn_recursive();
     }
+    commands.remove_resource::<KeyboardCaptured>();
 }
@@ -0,0 +1,158 @@
+use bevy::prelude::*;
+
+use crate::{
+    difficulty::DifficultyLevel,
+    settings::{Volume, WallTimerMode},
+    shake::ScreenShake,
+    GameMode,
+};
+
+use super::{spawn_button, ButtonAction};
+
+#[derive(Component)]
+pub struct SettingsScreen;
+
+const TITLE_TEXT: &str = "Settings";
+
+fn game_mode_label(game_mode: GameMode) -> String {
+    format!("Mode: {:?}", game_mode)
+}
+
+fn screen_shake_label(screen_shake: ScreenShake) -> String {
+    format!("Screen Shake: {:?}", screen_shake)
+}
+
+fn volume_label(volume: Volume) -> String {
+    format!("Volume: {:.0}%", volume.0 * 100.)
+}
+
+fn wall_timer_label(wall_timer_mode: WallTimerMode) -> String {
+    format!("Timer: {}", wall_timer_mode)
+}
+
+fn difficulty_label(difficulty_level: DifficultyLevel) -> String {
+    format!("Difficulty: {}", difficulty_level)
+}
+
+pub fn spawn_settings(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    game_mode: Res<GameMode>,
+    screen_shake: Res<ScreenShake>,
+    volume: Res<Volume>,
+    wall_timer_mode: Res<WallTimerMode>,
+    difficulty_level: Res<DifficultyLevel>,
+) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: BackgroundColor(Color::DARK_GRAY),
+                ..default()
+            },
+            SettingsScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn((TextBundle {
+                        style: Style {
+                            margin: UiRect::vertical(Val::Px(12.)),
+                            align_self: AlignSelf::Center,
+                            ..default()
+                        },
+                        text: Text::from_section(
+                            TITLE_TEXT,
+                            TextStyle {
+                                font: font.clone(),
+                                font_size: 48.0,
+                                color: Color::CRIMSON,
+                            },
+                        ),
+                        ..default()
+                    },));
+                    spawn_button(
+                        parent,
+                        font.clone(),
+                        &game_mode_label(*game_mode),
+                        ButtonAction::CycleGameMode,
+                    );
+                    spawn_button(
+                        parent,
+                        font.clone(),
+                        &difficulty_label(*difficulty_level),
+                        ButtonAction::CycleDifficulty,
+                    );
+                    spawn_button(
+                        parent,
+                        font.clone(),
+                        &wall_timer_label(*wall_timer_mode),
+                        ButtonAction::CycleWallTimerMode,
+                    );
+                    spawn_button(
+                        parent,
+                        font.clone(),
+                        &screen_shake_label(*screen_shake),
+                        ButtonAction::CycleScreenShake,
+                    );
+                    spawn_button(
+                        parent,
+                        font.clone(),
+                        &volume_label(*volume),
+                        ButtonAction::CycleVolume,
+                    );
+                    spawn_button(parent, font.clone(), "Back", ButtonAction::BackToMenu);
+                });
+        });
+}
+
+pub fn update_settings_labels(
+    game_mode: Res<GameMode>,
+    screen_shake: Res<ScreenShake>,
+    volume: Res<Volume>,
+    wall_timer_mode: Res<WallTimerMode>,
+    difficulty_level: Res<DifficultyLevel>,
+    button_query: Query<(&ButtonAction, &Children)>,
+    mut text_query: Query<&mut Text>,
+) {
+    if !game_mode.is_changed()
+        && !screen_shake.is_changed()
+        && !volume.is_changed()
+        && !wall_timer_mode.is_changed()
+        && !difficulty_level.is_changed()
+    {
+        return;
+    }
+    for (action, children) in button_query.iter() {
+        let label = match action {
+            ButtonAction::CycleGameMode => game_mode_label(*game_mode),
+            ButtonAction::CycleScreenShake => screen_shake_label(*screen_shake),
+            ButtonAction::CycleVolume => volume_label(*volume),
+            ButtonAction::CycleWallTimerMode => wall_timer_label(*wall_timer_mode),
+            ButtonAction::CycleDifficulty => difficulty_label(*difficulty_level),
+            _ => continue,
+        };
+        let mut texts = text_query.iter_many_mut(children);
+        while let Some(mut text) = texts.fetch_next() {
+            text.sections[0].value = label.clone();
+        }
+    }
+}
+
+pub fn despawn_settings(mut commands: Commands, query: Query<Entity, With<SettingsScreen>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,215 @@
+//! Simulates runs through the public API of the library, with no window.
+
+use bevy::{
+    app::PluginGroupBuilder,
+    input::{
+        gamepad::{
+            GamepadAxisChangedEvent, GamepadButtonChangedEvent, GamepadConnection,
+            GamepadConnectionEvent, GamepadEvent, GamepadInfo,
+        },
+        InputPlugin,
+    },
+    prelude::*,
+};
+use side_swap::{
+    actions::{Action, ActionPlugin, MenuAction},
+    effects::{ActiveEffects, Effect},
+    ghost::GhostPlugin,
+    pause::PausePlugin,
+    player::{Player, PlayerInput},
+    recording::RecordingPlugin,
+    rng::{NextRunSeed, RunSeed},
+    save::SavePlugin,
+    settings::KeyBindings,
+    tiles::Platform,
+    ui::UIPlugin,
+    AppState, SideSwapPlugins,
+};
+
+/// The plugins of the game that need no window or files.
+fn headless_plugins() -> PluginGroupBuilder {
+    SideSwapPlugins
+        .build()
+        // These read the window
+        .disable::<PausePlugin>()
+        .disable::<UIPlugin>()
+        // These read and write files in the data directory
+        .disable::<SavePlugin>()
+        .disable::<RecordingPlugin>()
+        .disable::<GhostPlugin>()
+}
+
+/// The game with no window, input or files, starting a run on `seed`.
+fn start_run(seed: u64) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        // This reads the keyboard and gamepads
+        .add_plugins(headless_plugins().disable::<ActionPlugin>());
+    begin_run(app, seed)
+}
+
+/// The game with no window or files, read from synthetic input events.
+fn start_run_with_input(seed: u64) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(InputPlugin)
+        .init_resource::<KeyBindings>()
+        .add_plugins(headless_plugins());
+    begin_run(app, seed)
+}
+
+fn begin_run(mut app: App, seed: u64) -> App {
+    app.insert_resource(NextRunSeed(Some(RunSeed(seed))));
+    app.world
+        .resource_mut::<NextState<AppState>>()
+        .set(AppState::InGame);
+    app.update();
+    app
+}
+
+fn step(app: &mut App, steps: usize) {
+    for _ in 0..steps {
+        app.world.run_schedule(CoreSchedule::FixedUpdate);
+    }
+}
+
+/// Sends a gamepad event the way the gamepad backend does, and lets the game read it.
+fn send_gamepad_event(app: &mut App, event: GamepadEvent) {
+    app.world.send_event(event);
+    app.update();
+}
+
+fn push_stick(app: &mut App, gamepad: Gamepad, axis_type: GamepadAxisType, value: f32) {
+    send_gamepad_event(
+        app,
+        GamepadEvent::Axis(GamepadAxisChangedEvent::new(gamepad, axis_type, value)),
+    );
+}
+
+fn press_button(app: &mut App, gamepad: Gamepad, button_type: GamepadButtonType, pressed: bool) {
+    let value = if pressed { 1. } else { 0. };
+    send_gamepad_event(
+        app,
+        GamepadEvent::Button(GamepadButtonChangedEvent::new(gamepad, button_type, value)),
+    );
+}
+
+fn pressed(app: &App) -> Vec<Action> {
+    let mut actions: Vec<Action> = app
+        .world
+        .resource::<Input<Action>>()
+        .get_pressed()
+        .copied()
+        .collect();
+    actions.sort_by_key(|action| *action as u8);
+    actions
+}
+
+fn platform_positions(app: &mut App) -> Vec<Vec2> {
+    let mut query = app
+        .world
+        .query_filtered::<&Transform, (With<Platform>, Without<Parent>)>();
+    query
+        .iter(&app.world)
+        .map(|transform| transform.translation.truncate())
+        .collect()
+}
+
+#[test]
+fn plays_a_run() {
+    let mut app = start_run(1);
+    assert_eq!(*app.world.resource::<RunSeed>(), RunSeed(1));
+    // Stepped by hand, the simulation does not depend on how fast the test runs
+    step(&mut app, 300);
+    app.update();
+    assert_eq!(app.world.resource::<State<AppState>>().0, AppState::InGame);
+    assert!(platform_positions(&mut app).len() > 1);
+
+    // Left alone, the player runs out of time before reaching the other wall
+    step(&mut app, 900);
+    app.update();
+    assert_eq!(app.world.resource::<State<AppState>>().0, AppState::YouDied);
+    let mut players = app.world.query_filtered::<(), With<Player>>();
+    assert_eq!(players.iter(&app.world).count(), 0);
+}
+
+#[test]
+fn same_seed_same_run() {
+    let mut first = start_run(42);
+    let mut second = start_run(42);
+    let mut other = start_run(43);
+    for app in [&mut first, &mut second, &mut other] {
+        step(app, 400);
+    }
+    let platforms = platform_positions(&mut first);
+    assert_eq!(platforms, platform_positions(&mut second));
+    assert_ne!(platforms, platform_positions(&mut other));
+}
+
+#[test]
+fn plays_with_a_gamepad() {
+    let mut app = start_run_with_input(1);
+    let gamepad = Gamepad::new(0);
+    send_gamepad_event(
+        &mut app,
+        GamepadEvent::Connection(GamepadConnectionEvent::new(
+            gamepad,
+            GamepadConnection::Connected(GamepadInfo {
+                name: "Test Gamepad".to_owned(),
+            }),
+        )),
+    );
+    assert!(app.world.resource::<Gamepads>().contains(gamepad));
+
+    // The stick only counts past the threshold
+    push_stick(&mut app, gamepad, GamepadAxisType::LeftStickX, 0.4);
+    assert_eq!(pressed(&app), []);
+    push_stick(&mut app, gamepad, GamepadAxisType::LeftStickX, 0.6);
+    assert_eq!(pressed(&app), [Action::MoveRight]);
+    push_stick(&mut app, gamepad, GamepadAxisType::LeftStickX, -0.6);
+    assert_eq!(pressed(&app), [Action::MoveLeft]);
+    push_stick(&mut app, gamepad, GamepadAxisType::LeftStickX, 0.);
+    press_button(&mut app, gamepad, GamepadButtonType::DPadRight, true);
+    assert_eq!(pressed(&app), [Action::MoveRight]);
+    press_button(&mut app, gamepad, GamepadButtonType::DPadRight, false);
+    press_button(&mut app, gamepad, GamepadButtonType::DPadLeft, true);
+    assert_eq!(pressed(&app), [Action::MoveLeft]);
+    press_button(&mut app, gamepad, GamepadButtonType::DPadLeft, false);
+    assert_eq!(pressed(&app), []);
+
+    press_button(&mut app, gamepad, GamepadButtonType::South, true);
+    assert_eq!(pressed(&app), [Action::Jump]);
+    assert!(app
+        .world
+        .resource::<Input<MenuAction>>()
+        .just_pressed(MenuAction::Activate));
+    press_button(&mut app, gamepad, GamepadButtonType::South, false);
+    press_button(&mut app, gamepad, GamepadButtonType::Start, true);
+    assert_eq!(pressed(&app), [Action::Pause]);
+    press_button(&mut app, gamepad, GamepadButtonType::Start, false);
+
+    // The menus follow the vertical stick and the D-pad
+    push_stick(&mut app, gamepad, GamepadAxisType::LeftStickY, -0.6);
+    assert!(app
+        .world
+        .resource::<Input<MenuAction>>()
+        .just_pressed(MenuAction::Next));
+    push_stick(&mut app, gamepad, GamepadAxisType::LeftStickY, 0.);
+    press_button(&mut app, gamepad, GamepadButtonType::DPadUp, true);
+    assert!(app
+        .world
+        .resource::<Input<MenuAction>>()
+        .just_pressed(MenuAction::Previous));
+    press_button(&mut app, gamepad, GamepadButtonType::DPadUp, false);
+
+    // Inverse Keyboard swaps the directions of the stick too
+    push_stick(&mut app, gamepad, GamepadAxisType::LeftStickX, 0.6);
+    app.world.resource_mut::<ActiveEffects>().0 = vec![Effect::InverseKeyboard];
+    step(&mut app, 1);
+    let input = *app.world.resource::<PlayerInput>();
+    assert!(input.left && !input.right);
+    app.world.resource_mut::<ActiveEffects>().0.clear();
+    step(&mut app, 1);
+    let input = *app.world.resource::<PlayerInput>();
+    assert!(input.right && !input.left);
+}